@@ -2,7 +2,9 @@ use godot::prelude::*;
 
 mod armor;
 mod map;
+mod network;
 mod players;
+mod transport;
 mod weapon;
 
 struct Client;
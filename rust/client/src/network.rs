@@ -0,0 +1,403 @@
+use std::collections::VecDeque;
+
+use godot::engine::Engine;
+use godot::prelude::*;
+use protocol::message::{self, ClientMessage, Encoding, ServerMessage, PROTOCOL_VERSION};
+use protocol::movement::{Buttons, UserCommand};
+use protocol::position::Position;
+use protocol::snapshot::Snapshot;
+
+use crate::players::player::Player;
+use crate::transport::Transport;
+
+/// How many received snapshots are kept around as baselines for deltas.
+const SNAPSHOT_HISTORY: usize = 64;
+
+#[derive(Debug, GodotClass)]
+#[class(init, base = Node)]
+pub struct Network {
+    #[export]
+    #[init(default = GString::from("127.0.0.1:7512"))]
+    address: GString,
+
+    #[export]
+    player: Option<Gd<Player>>,
+
+    /// The password of a private server, left empty for public ones.
+    #[export]
+    password: GString,
+
+    /// The encoding to ask the server for, "bincode" or "json" for debugging.
+    #[export]
+    #[init(default = GString::from("bincode"))]
+    encoding: GString,
+
+    /// How to reach the server, "tcp" or "udp", matching the transport it listens on.
+    #[export]
+    #[init(default = GString::from("tcp"))]
+    transport: GString,
+
+    connection: Option<Transport>,
+    /// The encoding agreed on with the server, which is JSON until it has welcomed us.
+    negotiated: Encoding,
+    /// Who we are playing as, once the server has welcomed us.
+    id: Option<usize>,
+    /// The token to reconnect with, once the server has handed one out.
+    token: Option<String>,
+    /// The most recent snapshots, oldest first.
+    snapshots: VecDeque<Snapshot>,
+    /// The number of commands sent over the current connection.
+    tick: u64,
+
+    base: Base<Node>,
+}
+
+#[godot_api]
+impl Network {
+    #[func]
+    pub fn connect_to_server(&mut self) -> bool {
+        let Some(player) = self.player.clone() else {
+            godot_error!("Network needs a player to connect with!");
+
+            return false;
+        };
+
+        if !self.open() {
+            return false;
+        }
+
+        let name = player.bind().name();
+        let password = Some(self.password.to_string()).filter(|password| !password.is_empty());
+        self.send(&ClientMessage::Hello {
+            version: PROTOCOL_VERSION,
+            name,
+            client_build: env!("CARGO_PKG_VERSION").to_string(),
+            encodings: self.encodings(),
+            password,
+        });
+
+        self.is_online()
+    }
+
+    /// Takes back our place in the match after losing the connection.
+    #[func]
+    pub fn reconnect_to_server(&mut self) -> bool {
+        let Some(token) = self.token.clone() else {
+            godot_error!("There is no session to reconnect to!");
+
+            return false;
+        };
+
+        if !self.open() {
+            return false;
+        }
+
+        self.send(&ClientMessage::Reconnect {
+            version: PROTOCOL_VERSION,
+            token,
+            encodings: self.encodings(),
+        });
+
+        self.is_online()
+    }
+
+    /// Tells the server whether we are ready for the match to start.
+    #[func]
+    pub fn set_ready(&mut self, ready: bool) {
+        self.send(&ClientMessage::Ready { ready });
+    }
+
+    #[func]
+    pub fn disconnect_from_server(&mut self) {
+        self.send(&ClientMessage::Disconnect);
+        self.flush();
+        self.connection = None;
+        self.token = None;
+    }
+
+    #[func]
+    pub fn is_online(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Returns the last known position of every player, keyed by their id.
+    #[func]
+    pub fn positions(&self) -> Dictionary {
+        let mut positions = Dictionary::new();
+        for player in self.snapshots.back().iter().flat_map(|s| &s.players) {
+            positions.set(player.id as i64, to_vector(&player.position));
+        }
+
+        positions
+    }
+
+    /// Opens a fresh connection to the server, dropping any old one.
+    fn open(&mut self) -> bool {
+        let address = self.address.to_string();
+        let connection = match Transport::connect(&address, &self.transport.to_string()) {
+            Ok(connection) => connection,
+            Err(error) => {
+                godot_error!("Failed to connect to {address}: {error}");
+
+                return false;
+            }
+        };
+
+        self.connection = Some(connection);
+        self.negotiated = Encoding::Json;
+        // Who we are, baselines and ticks from an old connection mean nothing to the new one.
+        self.id = None;
+        self.snapshots.clear();
+        self.tick = 0;
+
+        true
+    }
+
+    /// The encodings to offer the server, favourite first.
+    fn encodings(&self) -> Vec<Encoding> {
+        let preferred = self.encoding.to_string().parse().unwrap_or_else(|error| {
+            godot_warn!("{error}, falling back to JSON");
+
+            Encoding::Json
+        });
+
+        let mut encodings = vec![preferred];
+        if preferred != Encoding::Json {
+            encodings.push(Encoding::Json);
+        }
+
+        encodings
+    }
+
+    /// Queues a message, to be written to the socket on the next flush.
+    fn send(&mut self, message: &ClientMessage) {
+        let Some(connection) = self.connection.as_mut() else {
+            return;
+        };
+
+        // Until the server has welcomed us, it only understands the handshake.
+        if self.id.is_none() && !message.is_handshake() {
+            return;
+        }
+
+        let encoding = if message.is_handshake() {
+            Encoding::Json
+        } else {
+            self.negotiated
+        };
+
+        let result = message::encode(message, encoding)
+            .map_err(|error| error.to_string())
+            .and_then(|data| connection.send(&data, message.is_reliable()));
+        if let Err(error) = result {
+            godot_error!("Failed to send {message:?}: {error}");
+
+            self.connection = None;
+        }
+    }
+
+    /// Writes as much of what was queued as the socket takes without blocking, keeping the rest
+    /// for the next physics tick.
+    fn flush(&mut self) {
+        let Some(connection) = self.connection.as_mut() else {
+            return;
+        };
+
+        if let Err(error) = connection.flush() {
+            godot_error!("Failed to send: {error}");
+
+            self.connection = None;
+        }
+    }
+
+    fn receive(&mut self) -> Vec<ServerMessage> {
+        let Some(connection) = self.connection.as_mut() else {
+            return Vec::new();
+        };
+
+        // Drain everything the socket has buffered without blocking the frame.
+        let mut payloads = Vec::new();
+        if let Err(error) = connection.receive(&mut payloads) {
+            godot_print!("Lost the connection to the server: {error}");
+
+            self.connection = None;
+        }
+
+        let mut messages = Vec::new();
+        for payload in payloads {
+            match message::decode(&payload, self.negotiated) {
+                Ok(message) => {
+                    // Everything after the welcome is in the encoding it names.
+                    if let ServerMessage::Welcome { encoding, .. } = &message {
+                        self.negotiated = *encoding;
+                    }
+
+                    messages.push(message);
+                }
+                Err(error) => godot_error!("Failed to decode a message: {error}"),
+            }
+        }
+
+        messages
+    }
+
+    /// Keeps a snapshot around and lets the server know it can be used as a baseline.
+    fn store(&mut self, snapshot: Snapshot) {
+        let tick = snapshot.tick;
+
+        // The server has the final say on our health.
+        let state = self.id.and_then(|id| snapshot.player(id));
+        if let (Some(state), Some(player)) = (state, self.player.as_mut()) {
+            player.bind_mut().set_health(state.health);
+        }
+
+        if self.snapshots.len() == SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+
+        self.send(&ClientMessage::Ack { tick });
+    }
+
+    fn handle(&mut self, message: ServerMessage) {
+        match message {
+            ServerMessage::Snapshot(snapshot) => self.store(snapshot),
+            ServerMessage::Delta(delta) => {
+                let baseline = self.snapshots.iter().find(|s| s.tick == delta.baseline);
+
+                match baseline.map(|baseline| baseline.apply(&delta)) {
+                    Some(Ok(snapshot)) => self.store(snapshot),
+                    Some(Err(error)) => godot_error!("{error}"),
+                    None => godot_error!("Missing the baseline of tick {}", delta.baseline),
+                }
+            }
+            ServerMessage::Welcome {
+                id,
+                token,
+                position,
+                tick_rate,
+                hostname,
+                map,
+                mode,
+                ..
+            } => {
+                godot_print!("Playing {mode:?} on {map} at {hostname} as {id}");
+
+                // Send a command for every tick the server simulates.
+                Engine::singleton().set_physics_ticks_per_second(tick_rate as i32);
+
+                self.id = Some(id);
+                self.token = Some(token);
+                if let Some(mut player) = self.player.clone() {
+                    player.set_global_position(to_vector(&position));
+                }
+            }
+            ServerMessage::Correction { position } => {
+                if let Some(mut player) = self.player.clone() {
+                    player.set_global_position(to_vector(&position));
+                }
+            }
+            ServerMessage::Reject(rejection) => {
+                godot_error!("The server turned us down: {rejection:?}");
+
+                self.connection = None;
+                self.token = None;
+            }
+            ServerMessage::Event(event) => godot_print!("{event:?}"),
+            ServerMessage::Chat { id, text } => godot_print!("[{id}] {text}"),
+            ServerMessage::Disconnect { reason } => {
+                godot_print!("Disconnected by the server: {reason:?}");
+
+                self.connection = None;
+                if !reason.can_reconnect() {
+                    self.token = None;
+                }
+            }
+            ServerMessage::Shutdown { reason } => {
+                godot_print!("The server shut down: {reason}");
+
+                // There is nothing left to reconnect to.
+                self.connection = None;
+                self.token = None;
+            }
+            ServerMessage::Ping { time } => self.send(&ClientMessage::Pong { time }),
+            ServerMessage::Pong { .. } => {}
+        }
+    }
+}
+
+#[godot_api]
+impl INode for Network {
+    fn ready(&mut self) {
+        self.connect_to_server();
+    }
+
+    fn physics_process(&mut self, _delta: f64) {
+        for message in self.receive() {
+            self.handle(message);
+        }
+
+        if let Some(player) = self.player.clone() {
+            self.send_commands(player);
+        }
+
+        self.flush();
+    }
+}
+
+impl Network {
+    /// Sends what the local player did this tick, and the shots they fired.
+    fn send_commands(&mut self, player: Gd<Player>) {
+        let rotation = player.get_rotation();
+        let (yaw, pitch) = (rotation.y as f64, rotation.x as f64);
+
+        self.tick += 1;
+        self.send(&ClientMessage::Input(UserCommand {
+            tick: self.tick,
+            buttons: held_buttons(),
+            yaw,
+            pitch,
+            position: to_position(player.get_global_position()),
+        }));
+
+        let shots = player
+            .bind()
+            .weapon()
+            .map(|mut weapon| weapon.bind_mut().take_shots())
+            .unwrap_or_default();
+
+        // The server traces our shots through the world as we last saw it.
+        let tick = self.snapshots.back().map_or(0, |snapshot| snapshot.tick);
+        for _ in 0..shots {
+            self.send(&ClientMessage::Fire { tick, yaw, pitch });
+        }
+    }
+}
+
+/// The buttons the local player is holding down.
+fn held_buttons() -> Buttons {
+    let input = Input::singleton();
+
+    let mut buttons = Buttons::default();
+    for (action, button) in [
+        ("move_forward", Buttons::FORWARD),
+        ("move_backward", Buttons::BACKWARD),
+        ("move_left", Buttons::LEFT),
+        ("move_right", Buttons::RIGHT),
+        ("jump", Buttons::JUMP),
+    ] {
+        if input.is_action_pressed(action.into()) {
+            buttons.insert(button);
+        }
+    }
+
+    buttons
+}
+
+fn to_position(vector: Vector3) -> Position {
+    Position::new(vector.x as f64, vector.y as f64, vector.z as f64)
+}
+
+fn to_vector(position: &Position) -> Vector3 {
+    Vector3::new(position.x as f32, position.y as f32, position.z as f32)
+}
@@ -60,6 +60,16 @@ impl Player {
         self.health
     }
 
+    /// Takes on the health the server decided on, reacting to any damage taken.
+    #[func]
+    pub fn set_health(&mut self, health: f64) {
+        if health < self.health {
+            self.damage(self.health - health);
+        } else {
+            self.health = health;
+        }
+    }
+
     #[func]
     pub fn is_dead(&self) -> bool {
         self.health <= 0.0
@@ -108,18 +118,12 @@ impl Player {
         velocity
     }
 
-    #[func]
-    pub fn damage(&mut self, damage: f64) {
+    /// Reacts to damage the server says we took. The match carries on, as the server decides
+    /// when we respawn.
+    fn damage(&mut self, damage: f64) {
         self.health -= damage.min(self.health);
 
         if self.is_dead() {
-            // Show death screen.
-            if let Some(mut tree) = self.base().get_tree() {
-                godot_print!("Pausing...");
-
-                tree.set_pause(true);
-            }
-
             godot_print!("I'm dead!");
 
             return;
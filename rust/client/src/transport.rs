@@ -0,0 +1,215 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use protocol::channel::Endpoint;
+use protocol::frame::{self, FrameDecoder};
+
+/// How many bytes may wait for the socket before we give up on the server.
+const MAX_OUTGOING: usize = 256 * 1024;
+
+/// How long the server may stay silent over UDP before we give up on it.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A connection to the server over whichever transport it listens on, carrying whole payloads.
+///
+/// Nothing here blocks: sends are queued until the next flush, and receiving only takes what has
+/// already arrived.
+#[derive(Debug)]
+pub enum Transport {
+    Tcp {
+        stream: TcpStream,
+        decoder: FrameDecoder,
+        /// Frames the socket has yet to take, which it may only do in part without blocking.
+        outgoing: Vec<u8>,
+    },
+    Udp {
+        socket: UdpSocket,
+        endpoint: Endpoint,
+        /// When the server last sent us anything.
+        last_heard: Instant,
+    },
+}
+
+impl Transport {
+    /// Connects to the server at `address` over `kind`, which is either "tcp" or "udp".
+    pub fn connect(address: &str, kind: &str) -> Result<Self, String> {
+        let address = resolve(address)?;
+
+        let transport = match kind {
+            "tcp" => {
+                let stream = TcpStream::connect(address).map_err(|error| error.to_string())?;
+                stream
+                    .set_nodelay(true)
+                    .and_then(|_| stream.set_nonblocking(true))
+                    .map_err(|error| error.to_string())?;
+
+                Self::Tcp {
+                    stream,
+                    decoder: FrameDecoder::new(),
+                    outgoing: Vec::new(),
+                }
+            }
+            "udp" => {
+                let local: SocketAddr = if address.is_ipv6() {
+                    "[::]:0".parse().expect("valid address")
+                } else {
+                    "0.0.0.0:0".parse().expect("valid address")
+                };
+
+                let socket = UdpSocket::bind(local).map_err(|error| error.to_string())?;
+                socket
+                    .connect(address)
+                    .and_then(|_| socket.set_nonblocking(true))
+                    .map_err(|error| error.to_string())?;
+
+                Self::Udp {
+                    socket,
+                    endpoint: Endpoint::new(),
+                    last_heard: Instant::now(),
+                }
+            }
+            _ => return Err(format!("Unknown transport {kind:?}, expected tcp or udp")),
+        };
+
+        Ok(transport)
+    }
+
+    /// Queues a payload for the server. Over UDP, reliable ones are sent again until the server
+    /// acknowledges them, and the rest are sent once.
+    pub fn send(&mut self, payload: &[u8], reliable: bool) -> Result<(), String> {
+        match self {
+            Self::Tcp { outgoing, .. } => {
+                let frame = frame::encode(payload).map_err(|error| error.to_string())?;
+                outgoing.extend_from_slice(&frame);
+
+                if outgoing.len() > MAX_OUTGOING {
+                    return Err("the server is not taking our messages".to_string());
+                }
+
+                Ok(())
+            }
+            Self::Udp {
+                socket, endpoint, ..
+            } => {
+                let datagram = if reliable {
+                    endpoint.send_reliable(payload.to_vec(), Instant::now())
+                } else {
+                    endpoint.send_unreliable(payload.to_vec())
+                };
+
+                transmit(socket, &datagram.map_err(|error| error.to_string())?)
+            }
+        }
+    }
+
+    /// Writes as much of what was queued as the socket takes without blocking, and sends again
+    /// what the server has yet to acknowledge.
+    pub fn flush(&mut self) -> Result<(), String> {
+        match self {
+            Self::Tcp {
+                stream, outgoing, ..
+            } => {
+                while !outgoing.is_empty() {
+                    match stream.write(outgoing) {
+                        Ok(0) => return Err("the server closed the connection".to_string()),
+                        Ok(size) => {
+                            outgoing.drain(..size);
+                        }
+                        Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                        Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                        Err(error) => return Err(error.to_string()),
+                    }
+                }
+
+                Ok(())
+            }
+            Self::Udp {
+                socket, endpoint, ..
+            } => {
+                for datagram in endpoint.resend(Instant::now()) {
+                    transmit(socket, &datagram)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Adds the payloads that have arrived to `payloads`, failing once the connection is gone.
+    ///
+    /// Payloads that arrived before the connection went away are still added.
+    pub fn receive(&mut self, payloads: &mut Vec<Vec<u8>>) -> Result<(), String> {
+        match self {
+            Self::Tcp {
+                stream, decoder, ..
+            } => {
+                let mut buffer = [0; 4096];
+                let result = loop {
+                    match stream.read(&mut buffer) {
+                        Ok(0) => break Err("the server closed the connection".to_string()),
+                        Ok(size) => decoder.extend(&buffer[..size]),
+                        Err(error) if error.kind() == ErrorKind::WouldBlock => break Ok(()),
+                        Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                        Err(error) => break Err(error.to_string()),
+                    }
+                };
+
+                while let Some(frame) = decoder.next_frame().map_err(|error| error.to_string())? {
+                    payloads.push(frame);
+                }
+
+                result
+            }
+            Self::Udp {
+                socket,
+                endpoint,
+                last_heard,
+            } => {
+                let mut buffer = vec![0; u16::MAX as usize];
+                loop {
+                    let size = match socket.recv(&mut buffer) {
+                        Ok(size) => size,
+                        Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                        Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                        Err(error) => return Err(error.to_string()),
+                    };
+
+                    *last_heard = Instant::now();
+
+                    let received = endpoint
+                        .receive(&buffer[..size])
+                        .map_err(|error| error.to_string())?;
+                    if let Some(reply) = received.reply {
+                        transmit(socket, &reply)?;
+                    }
+
+                    payloads.extend(received.payloads);
+                }
+
+                if last_heard.elapsed() > TIMEOUT {
+                    return Err("the server stopped answering".to_string());
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    address
+        .to_socket_addrs()
+        .map_err(|error| error.to_string())?
+        .next()
+        .ok_or_else(|| format!("{address} does not resolve to any address"))
+}
+
+/// Sends a datagram, dropping it if the socket is busy since UDP may lose it anyway.
+fn transmit(socket: &UdpSocket, datagram: &[u8]) -> Result<(), String> {
+    match socket.send(datagram) {
+        Ok(_) => Ok(()),
+        Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(()),
+        Err(error) => Err(error.to_string()),
+    }
+}
@@ -0,0 +1,23 @@
+/// The stats of a piece of armor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Armor {
+    /// The share of incoming damage the armor soaks up, between 0.0 and 1.0.
+    pub damage_mitigation: f64,
+    pub max_durability: f64,
+}
+
+pub const BASIC_HELMET: Armor = Armor {
+    damage_mitigation: 0.1,
+    max_durability: 10.0,
+};
+
+impl Armor {
+    /// Soaks up part of `damage`, wearing down `durability`, and returns the damage that gets
+    /// through.
+    pub fn absorb(&self, damage: f64, durability: &mut f64) -> f64 {
+        let mitigated = (damage * self.damage_mitigation).min(*durability);
+        *durability -= mitigated;
+
+        damage - mitigated
+    }
+}
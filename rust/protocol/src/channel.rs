@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use crate::packet::Packet;
+use crate::Error;
+
+/// How long to wait for an ack before sending a reliable packet again.
+pub const RESEND_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How far ahead of the next expected reliable packet we are willing to buffer.
+const RECEIVE_WINDOW: u32 = 256;
+
+/// How many reliable packets may wait for an ack before the other side is given up on.
+pub const SEND_WINDOW: usize = 256;
+
+/// One side of a UDP connection, keeping track of sequence numbers, acks and resends.
+///
+/// This does no IO itself: it turns payloads into datagrams to send and received datagrams back
+/// into payloads, so both the server and the client can drive it however they like.
+#[derive(Debug, Default)]
+pub struct Endpoint {
+    next_reliable: u32,
+    next_unreliable: u32,
+    /// Reliable packets that have not been acknowledged yet, and when they were last sent.
+    unacked: BTreeMap<u32, (Instant, Vec<u8>)>,
+
+    /// The sequence number of the next reliable packet to deliver.
+    expected: u32,
+    /// Reliable packets that arrived before the ones in front of them.
+    early: BTreeMap<u32, Vec<u8>>,
+    /// The sequence number of the newest unreliable packet delivered so far.
+    latest: Option<u32>,
+}
+
+/// The outcome of receiving a single datagram.
+#[derive(Debug, Default)]
+pub struct Received {
+    /// A datagram to send back, if the packet needs to be acknowledged.
+    pub reply: Option<Vec<u8>>,
+    /// The payloads that can now be delivered, in order.
+    pub payloads: Vec<Vec<u8>>,
+}
+
+impl Endpoint {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps `payload` into a datagram that will be resent until acknowledged.
+    ///
+    /// Fails once the send window is full, as the other side has stopped acknowledging.
+    pub fn send_reliable(&mut self, payload: Vec<u8>, now: Instant) -> Result<Vec<u8>, Error> {
+        if self.unacked.len() >= SEND_WINDOW {
+            return Err(Error::SendWindowFull);
+        }
+
+        let sequence = self.next_reliable;
+        let datagram = Packet::Reliable { sequence, payload }.encode()?;
+
+        self.next_reliable = self.next_reliable.wrapping_add(1);
+        self.unacked.insert(sequence, (now, datagram.clone()));
+
+        Ok(datagram)
+    }
+
+    /// Wraps `payload` into a datagram that is sent only once.
+    pub fn send_unreliable(&mut self, payload: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let sequence = self.next_unreliable;
+        self.next_unreliable = self.next_unreliable.wrapping_add(1);
+
+        Packet::Unreliable { sequence, payload }.encode()
+    }
+
+    pub fn receive(&mut self, datagram: &[u8]) -> Result<Received, Error> {
+        let mut received = Received::default();
+
+        match Packet::decode(datagram)? {
+            Packet::Reliable { sequence, payload } => {
+                // Always acknowledge, in case our previous ack was the one that got lost.
+                received.reply = Some(Packet::Ack { sequence }.encode()?);
+
+                let ahead = sequence.wrapping_sub(self.expected);
+                if ahead < RECEIVE_WINDOW {
+                    self.early.insert(sequence, payload);
+                }
+
+                while let Some(payload) = self.early.remove(&self.expected) {
+                    received.payloads.push(payload);
+                    self.expected = self.expected.wrapping_add(1);
+                }
+            }
+            Packet::Unreliable { sequence, payload } => {
+                let is_newer = match self.latest {
+                    Some(latest) => (sequence.wrapping_sub(latest) as i32) > 0,
+                    None => true,
+                };
+
+                if is_newer {
+                    self.latest = Some(sequence);
+                    received.payloads.push(payload);
+                }
+            }
+            Packet::Ack { sequence } => {
+                self.unacked.remove(&sequence);
+            }
+        }
+
+        Ok(received)
+    }
+
+    /// Returns the reliable datagrams that have waited too long for an ack and should be sent again.
+    pub fn resend(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        self.unacked
+            .values_mut()
+            .filter(|(sent, _)| now.duration_since(*sent) >= RESEND_TIMEOUT)
+            .map(|(sent, datagram)| {
+                *sent = now;
+
+                datagram.clone()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends every payload reliably, returning the datagrams in the order they were sent.
+    fn send_all(sender: &mut Endpoint, payloads: &[&[u8]], now: Instant) -> Vec<Vec<u8>> {
+        payloads
+            .iter()
+            .map(|payload| sender.send_reliable(payload.to_vec(), now).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn delivers_reordered_reliable_packets_in_order() {
+        let now = Instant::now();
+        let (mut sender, mut receiver) = (Endpoint::new(), Endpoint::new());
+        let datagrams = send_all(&mut sender, &[b"a", b"b", b"c"], now);
+
+        let received = receiver.receive(&datagrams[2]).unwrap();
+        assert!(received.payloads.is_empty());
+        assert!(received.reply.is_some());
+
+        let received = receiver.receive(&datagrams[0]).unwrap();
+        assert_eq!(received.payloads, [b"a".to_vec()]);
+
+        let received = receiver.receive(&datagrams[1]).unwrap();
+        assert_eq!(received.payloads, [b"b".to_vec(), b"c".to_vec()]);
+
+        // A duplicate is acknowledged again, but not delivered twice.
+        let received = receiver.receive(&datagrams[1]).unwrap();
+        assert!(received.payloads.is_empty());
+        assert!(received.reply.is_some());
+    }
+
+    #[test]
+    fn reliable_sequence_numbers_wrap_around() {
+        let now = Instant::now();
+        let mut sender = Endpoint {
+            next_reliable: u32::MAX - 1,
+            ..Endpoint::default()
+        };
+        let mut receiver = Endpoint {
+            expected: u32::MAX - 1,
+            ..Endpoint::default()
+        };
+
+        let datagrams = send_all(&mut sender, &[b"a", b"b", b"c", b"d"], now);
+        let mut payloads = Vec::new();
+        for datagram in datagrams.iter().rev() {
+            payloads.extend(receiver.receive(datagram).unwrap().payloads);
+        }
+
+        let expected: Vec<Vec<u8>> =
+            vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()];
+        assert_eq!(payloads, expected);
+        assert_eq!(receiver.expected, 2);
+    }
+
+    #[test]
+    fn ignores_reliable_packets_too_far_ahead() {
+        let mut receiver = Endpoint::new();
+        let datagram = Packet::Reliable {
+            sequence: RECEIVE_WINDOW,
+            payload: b"a".to_vec(),
+        }
+        .encode()
+        .unwrap();
+
+        receiver.receive(&datagram).unwrap();
+        assert!(receiver.early.is_empty());
+    }
+
+    #[test]
+    fn drops_unreliable_packets_older_than_the_latest_across_wraparound() {
+        let mut sender = Endpoint {
+            next_unreliable: u32::MAX,
+            ..Endpoint::default()
+        };
+        let mut receiver = Endpoint::new();
+
+        let old = sender.send_unreliable(b"old".to_vec()).unwrap();
+        let new = sender.send_unreliable(b"new".to_vec()).unwrap();
+
+        let received = receiver.receive(&new).unwrap();
+        assert_eq!(received.payloads, [b"new".to_vec()]);
+        assert!(received.reply.is_none());
+
+        assert!(receiver.receive(&old).unwrap().payloads.is_empty());
+    }
+
+    #[test]
+    fn resends_until_acknowledged() {
+        let now = Instant::now();
+        let (mut sender, mut receiver) = (Endpoint::new(), Endpoint::new());
+        let datagram = sender.send_reliable(b"a".to_vec(), now).unwrap();
+
+        assert!(sender.resend(now).is_empty());
+        assert_eq!(sender.resend(now + RESEND_TIMEOUT), vec![datagram.clone()]);
+
+        let ack = receiver.receive(&datagram).unwrap().reply.unwrap();
+        sender.receive(&ack).unwrap();
+        assert!(sender.resend(now + RESEND_TIMEOUT * 2).is_empty());
+    }
+
+    #[test]
+    fn refuses_to_send_once_the_window_is_full() {
+        let now = Instant::now();
+        let mut sender = Endpoint::new();
+        for _ in 0..SEND_WINDOW {
+            sender.send_reliable(Vec::new(), now).unwrap();
+        }
+
+        assert!(matches!(
+            sender.send_reliable(Vec::new(), now),
+            Err(Error::SendWindowFull)
+        ));
+    }
+}
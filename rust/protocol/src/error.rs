@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Serde error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("Bincode error: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[error("Unknown encoding {0:?}")]
+    UnknownEncoding(String),
+    #[error("Unknown game mode {0:?}")]
+    UnknownGameMode(String),
+    #[error("Invalid duration {0:?}, expected a number of s, m, h or d")]
+    InvalidDuration(String),
+    #[error("Frame of {0} bytes exceeds the maximum of {max} bytes", max = crate::frame::MAX_FRAME_SIZE)]
+    FrameTooLarge(usize),
+    #[error("Packet payload of {0} bytes exceeds the maximum of {max} bytes", max = crate::packet::MAX_PAYLOAD_SIZE)]
+    PacketTooLarge(usize),
+    #[error("Invalid packet")]
+    InvalidPacket,
+    #[error("Too many reliable packets are waiting for an ack")]
+    SendWindowFull,
+    #[error("Delta does not apply to the snapshot of tick {0}")]
+    InvalidDelta(u64),
+}
@@ -0,0 +1,114 @@
+use crate::Error;
+
+/// The largest frame either side is willing to read or write, in bytes.
+pub const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+/// The size of the length prefix in front of every frame, in bytes.
+pub const HEADER_SIZE: usize = 4;
+
+/// Prefixes `data` with its length as a big-endian `u32`.
+pub fn encode(data: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() > MAX_FRAME_SIZE {
+        return Err(Error::FrameTooLarge(data.len()));
+    }
+
+    let mut frame = Vec::with_capacity(HEADER_SIZE + data.len());
+    frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    frame.extend_from_slice(data);
+
+    Ok(frame)
+}
+
+/// Splits a byte stream back into frames, no matter how it was chunked on the way.
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends freshly received bytes.
+    pub fn extend(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Takes the next complete frame, if one has fully arrived.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        let Some(header) = self.buffer.get(..HEADER_SIZE) else {
+            return Ok(None);
+        };
+
+        let size = u32::from_be_bytes(header.try_into().expect("header has a fixed size")) as usize;
+        if size > MAX_FRAME_SIZE {
+            return Err(Error::FrameTooLarge(size));
+        }
+
+        if self.buffer.len() < HEADER_SIZE + size {
+            return Ok(None);
+        }
+
+        let frame = self.buffer[HEADER_SIZE..HEADER_SIZE + size].to_vec();
+        self.buffer.drain(..HEADER_SIZE + size);
+
+        Ok(Some(frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_frames_split_at_every_byte() {
+        let mut stream = encode(b"hello").unwrap();
+        stream.extend(encode(b"").unwrap());
+        stream.extend(encode(b"world").unwrap());
+
+        let mut decoder = FrameDecoder::new();
+        let mut frames = Vec::new();
+        for byte in &stream {
+            decoder.extend(std::slice::from_ref(byte));
+            while let Some(frame) = decoder.next_frame().unwrap() {
+                frames.push(frame);
+            }
+        }
+
+        assert_eq!(frames, [b"hello".to_vec(), Vec::new(), b"world".to_vec()]);
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_frame() {
+        let frame = encode(b"hello").unwrap();
+
+        let mut decoder = FrameDecoder::new();
+        decoder.extend(&frame[..HEADER_SIZE + 2]);
+        assert_eq!(decoder.next_frame().unwrap(), None);
+
+        decoder.extend(&frame[HEADER_SIZE + 2..]);
+        assert_eq!(decoder.next_frame().unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(decoder.next_frame().unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_oversized_frames_before_they_arrive() {
+        let mut decoder = FrameDecoder::new();
+        decoder.extend(&(MAX_FRAME_SIZE as u32 + 1).to_be_bytes());
+
+        assert!(matches!(
+            decoder.next_frame(),
+            Err(Error::FrameTooLarge(size)) if size == MAX_FRAME_SIZE + 1
+        ));
+    }
+
+    #[test]
+    fn refuses_to_encode_oversized_frames() {
+        assert!(encode(&vec![0; MAX_FRAME_SIZE]).is_ok());
+        assert!(matches!(
+            encode(&vec![0; MAX_FRAME_SIZE + 1]),
+            Err(Error::FrameTooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,13 @@
+pub use error::Error;
+
+pub mod armor;
+pub mod channel;
+pub mod error;
+pub mod frame;
+pub mod message;
+pub mod movement;
+pub mod packet;
+pub mod position;
+pub mod rcon;
+pub mod snapshot;
+pub mod weapon;
@@ -0,0 +1,310 @@
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::movement::UserCommand;
+use crate::position::Position;
+use crate::snapshot::{Delta, Snapshot};
+use crate::Error;
+
+/// The version of the protocol spoken by both sides.
+///
+/// Bump this whenever the shape of a message changes.
+pub const PROTOCOL_VERSION: u32 = 21;
+
+/// The most characters a player's name may have.
+pub const MAX_NAME_LENGTH: usize = 24;
+
+/// The most characters of a chat message passed on to other players.
+pub const MAX_CHAT_LENGTH: usize = 200;
+
+/// Serializes a message into the payload of a frame.
+pub fn encode<T: Serialize>(message: &T, encoding: Encoding) -> Result<Vec<u8>, Error> {
+    match encoding {
+        Encoding::Json => serde_json::to_vec(message).map_err(Into::into),
+        Encoding::Bincode => bincode::serialize(message).map_err(Into::into),
+    }
+}
+
+/// Deserializes a message from the payload of a frame.
+pub fn decode<T: DeserializeOwned>(data: &[u8], encoding: Encoding) -> Result<T, Error> {
+    match encoding {
+        Encoding::Json => serde_json::from_slice(data).map_err(Into::into),
+        Encoding::Bincode => bincode::deserialize(data).map_err(Into::into),
+    }
+}
+
+/// How messages are turned into bytes, as agreed on during the handshake.
+///
+/// The handshake itself is always JSON, so both sides can read it before they have agreed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encoding {
+    /// Readable, which is handy for debugging.
+    #[default]
+    Json,
+    /// Compact and quick to encode.
+    Bincode,
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "json" => Ok(Self::Json),
+            "bincode" => Ok(Self::Bincode),
+            _ => Err(Error::UnknownEncoding(name.to_string())),
+        }
+    }
+}
+
+/// The rules a match is played by.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    /// Everyone for themselves.
+    #[default]
+    Deathmatch,
+}
+
+impl FromStr for GameMode {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "deathmatch" => Ok(Self::Deathmatch),
+            _ => Err(Error::UnknownGameMode(name.to_string())),
+        }
+    }
+}
+
+/// A message sent from a client to the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// The first message of every connection.
+    Hello {
+        version: u32,
+        name: String,
+        /// The version of the game the client is running, for the server's logs.
+        client_build: String,
+        /// The encodings the client speaks, favourite first.
+        encodings: Vec<Encoding>,
+        /// The password of a private server.
+        password: Option<String>,
+    },
+    /// Takes back the place of a player whose connection was lost, instead of saying hello.
+    Reconnect {
+        version: u32,
+        token: String,
+        encodings: Vec<Encoding>,
+    },
+    /// Tells the server whether the player is ready for the match to start.
+    Ready {
+        ready: bool,
+    },
+    /// What the player did during one of their client's ticks.
+    Input(UserCommand),
+    /// Tells the server the player fired their weapon, looking in the given direction while
+    /// seeing the snapshot of the given tick.
+    ///
+    /// The server decides whether the shot was possible, what it hit and how much damage it deals.
+    Fire {
+        tick: u64,
+        yaw: f64,
+        pitch: f64,
+    },
+    /// Tells the server the client has the snapshot of the given tick, so it can be used as a
+    /// baseline for deltas.
+    Ack {
+        tick: u64,
+    },
+    /// Says something to every player. The server trims the text and cuts it down to
+    /// `MAX_CHAT_LENGTH` characters, with line breaks and other control characters as spaces.
+    Chat {
+        text: String,
+    },
+    Disconnect,
+    Ping {
+        time: u64,
+    },
+    Pong {
+        time: u64,
+    },
+}
+
+impl ClientMessage {
+    /// Whether the message has to arrive, or may be dropped in favour of a newer one.
+    pub fn is_reliable(&self) -> bool {
+        !matches!(self, Self::Input(_) | Self::Ack { .. })
+    }
+
+    /// Whether the message starts a handshake, and so is always sent as JSON.
+    pub fn is_handshake(&self) -> bool {
+        matches!(self, Self::Hello { .. } | Self::Reconnect { .. })
+    }
+}
+
+/// A message sent from the server to a client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// Accepts a handshake, telling the player who they are, where they are, and the token to
+    /// reconnect with should their connection drop.
+    Welcome {
+        id: usize,
+        token: String,
+        position: Position,
+        /// How many ticks the server simulates per second, which the client should send
+        /// commands at.
+        tick_rate: u32,
+        /// The encoding both sides use from here on.
+        encoding: Encoding,
+        /// The name of the server, for the client to show.
+        hostname: String,
+        /// The map the match is played on.
+        map: String,
+        mode: GameMode,
+    },
+    /// Turns down a handshake, after which the connection is closed.
+    Reject(Rejection),
+    Snapshot(Snapshot),
+    /// Puts the player back where the server last accepted them, after a move it turned down.
+    Correction {
+        position: Position,
+    },
+    /// The changes since a snapshot the client has acknowledged.
+    Delta(Delta),
+    Event(Event),
+    Chat {
+        id: usize,
+        text: String,
+    },
+    Disconnect {
+        reason: DisconnectReason,
+    },
+    /// The server is going away, and everyone with it.
+    Shutdown {
+        reason: String,
+    },
+    Ping {
+        time: u64,
+    },
+    Pong {
+        time: u64,
+    },
+}
+
+impl ServerMessage {
+    /// Whether the message has to arrive, or may be dropped in favour of a newer one.
+    pub fn is_reliable(&self) -> bool {
+        !matches!(self, Self::Snapshot(_) | Self::Delta(_))
+    }
+
+    /// Whether the message answers a handshake, and so is always sent as JSON.
+    pub fn is_handshake(&self) -> bool {
+        matches!(self, Self::Welcome { .. } | Self::Reject(_))
+    }
+}
+
+/// Something that happened in the match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Event {
+    Joined {
+        id: usize,
+        name: String,
+    },
+    Left {
+        id: usize,
+        reason: DisconnectReason,
+    },
+    Ready {
+        id: usize,
+        ready: bool,
+    },
+    Killed {
+        id: usize,
+        by: usize,
+        headshot: bool,
+    },
+    /// Everyone is ready, and the match starts in the given number of seconds.
+    Countdown {
+        seconds: f64,
+    },
+    /// Someone is no longer ready, so the lobby is back to waiting.
+    CountdownCancelled,
+    MatchStarted,
+    /// The round ran out of time, and everyone is back in the lobby.
+    RoundEnded,
+    /// An admin started the round over, with everyone back at their spawn.
+    RoundRestarted,
+    /// The match has been switched to another map, and the round restarted on it.
+    MapChanged {
+        map: String,
+    },
+    /// An admin froze or unfroze the match.
+    Paused {
+        paused: bool,
+    },
+    /// A message from the server's admins.
+    Announcement {
+        text: String,
+    },
+}
+
+/// Why a player left the match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisconnectReason {
+    /// The player left on their own.
+    Quit,
+    /// The connection broke or was closed.
+    ConnectionLost,
+    /// Nothing was heard from the player for too long.
+    TimedOut,
+    /// The player sent something that is not a valid message.
+    InvalidMessage,
+    /// The player sent a message larger than the server accepts.
+    MessageTooLarge,
+    /// The player sent a valid message at the wrong time.
+    UnexpectedMessage,
+    /// The player could not keep up with the messages sent to them.
+    Stalled,
+    /// The player kept sending more than the server allows.
+    Flooding,
+    /// The player reported moves they could not have made.
+    IllegalMovement,
+    /// An admin removed the player.
+    Kicked,
+    /// An admin removed the player for good.
+    Banned,
+}
+
+/// Why a handshake was turned down.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rejection {
+    /// The client speaks a different version of the protocol than the server.
+    VersionMismatch { server: u32 },
+    /// There is no room left for another player.
+    ServerFull,
+    /// The client tried to reconnect to a session that is unknown or has expired.
+    InvalidSession,
+    /// The server is private, and the client did not have the right password.
+    BadPassword,
+    /// The client got the password wrong too many times, and has to wait before trying again.
+    TooManyAttempts,
+    /// The client's address or name has been banned from the server.
+    Banned {
+        reason: Option<String>,
+        /// When the ban runs out, in seconds since the Unix epoch, or never.
+        expires: Option<u64>,
+    },
+    /// The name is empty, longer than `MAX_NAME_LENGTH`, or has characters that cannot be shown.
+    InvalidName,
+    /// Someone in the match, or holding a place in it, already goes by that name.
+    NameTaken,
+}
+
+impl DisconnectReason {
+    /// Whether the player keeps their place for a while, in case they reconnect.
+    pub fn can_reconnect(&self) -> bool {
+        matches!(self, Self::ConnectionLost | Self::TimedOut | Self::Stalled)
+    }
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+use crate::position::Position;
+
+/// The buttons held down during a command, as a set of flags.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Buttons(pub u8);
+
+impl Buttons {
+    pub const FORWARD: Self = Self(1 << 0);
+    pub const BACKWARD: Self = Self(1 << 1);
+    pub const LEFT: Self = Self(1 << 2);
+    pub const RIGHT: Self = Self(1 << 3);
+    pub const JUMP: Self = Self(1 << 4);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// What a player did during a single tick of their client.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct UserCommand {
+    /// The client's tick this command was made in, increasing by one every tick.
+    pub tick: u64,
+    pub buttons: Buttons,
+    /// Where the player is looking, in radians.
+    pub yaw: f64,
+    pub pitch: f64,
+    /// Where the client thinks the player ended up, used when the server trusts clients with
+    /// their own movement.
+    pub position: Position,
+}
+
+/// How fast a player moves, matching the properties the Godot `Player` exports.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Movement {
+    pub run_speed: f64,
+    pub jump_force: f64,
+    pub gravity: f64,
+}
+
+pub const DEFAULT_MOVEMENT: Movement = Movement {
+    run_speed: 10.0,
+    jump_force: 5.0,
+    gravity: 9.8,
+};
+
+/// The part of a player that movement acts on.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Body {
+    pub position: Position,
+    pub velocity: Position,
+}
+
+impl Body {
+    pub fn new(position: Position) -> Self {
+        Self {
+            position,
+            velocity: Position::ZERO,
+        }
+    }
+
+    /// Whether the body stands on a floor at the given height.
+    pub fn is_on_floor(&self, floor: f64) -> bool {
+        self.position.y <= floor
+    }
+}
+
+impl Movement {
+    /// How far above the floor a jump reaches.
+    pub fn jump_height(&self) -> f64 {
+        self.jump_force * self.jump_force / (2.0 * self.gravity)
+    }
+
+    /// Moves `body` for `delta` seconds with the given buttons held, stopping it at the floor.
+    ///
+    /// Like the Godot `Player`, the movement keys move along the world's axes.
+    pub fn simulate(&self, body: &mut Body, buttons: Buttons, delta: f64, floor: f64) {
+        let mut velocity = Position::new(0.0, body.velocity.y - self.gravity * delta, 0.0);
+
+        if buttons.contains(Buttons::FORWARD) {
+            velocity.z -= self.run_speed;
+        }
+        if buttons.contains(Buttons::BACKWARD) {
+            velocity.z += self.run_speed;
+        }
+        if buttons.contains(Buttons::LEFT) {
+            velocity.x -= self.run_speed;
+        }
+        if buttons.contains(Buttons::RIGHT) {
+            velocity.x += self.run_speed;
+        }
+        if buttons.contains(Buttons::JUMP) && body.is_on_floor(floor) {
+            velocity.y = self.jump_force;
+        }
+
+        body.position += velocity * delta;
+        if body.position.y < floor {
+            body.position.y = floor;
+            velocity.y = 0.0;
+        }
+
+        body.velocity = velocity;
+    }
+}
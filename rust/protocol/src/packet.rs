@@ -0,0 +1,66 @@
+use crate::Error;
+
+/// The size of the header in front of every packet's payload, in bytes.
+pub const HEADER_SIZE: usize = 5;
+
+/// The largest payload that fits into a single UDP datagram, in bytes.
+pub const MAX_PAYLOAD_SIZE: usize = 65_507 - HEADER_SIZE;
+
+const RELIABLE: u8 = 0;
+const UNRELIABLE: u8 = 1;
+const ACK: u8 = 2;
+
+/// A single datagram of the UDP transport.
+///
+/// Every packet starts with a one byte kind and a big-endian `u32` sequence number, followed by
+/// the payload, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Packet {
+    /// Resent until acknowledged, and delivered in order.
+    Reliable { sequence: u32, payload: Vec<u8> },
+    /// Sent once, and dropped if anything newer has already been delivered.
+    Unreliable { sequence: u32, payload: Vec<u8> },
+    /// Acknowledges the reliable packet with the same sequence number.
+    Ack { sequence: u32 },
+}
+
+impl Packet {
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        let (kind, sequence, payload) = match self {
+            Self::Reliable { sequence, payload } => (RELIABLE, *sequence, payload.as_slice()),
+            Self::Unreliable { sequence, payload } => (UNRELIABLE, *sequence, payload.as_slice()),
+            Self::Ack { sequence } => (ACK, *sequence, [].as_slice()),
+        };
+
+        if payload.len() > MAX_PAYLOAD_SIZE {
+            return Err(Error::PacketTooLarge(payload.len()));
+        }
+
+        let mut data = Vec::with_capacity(HEADER_SIZE + payload.len());
+        data.push(kind);
+        data.extend_from_slice(&sequence.to_be_bytes());
+        data.extend_from_slice(payload);
+
+        Ok(data)
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < HEADER_SIZE {
+            return Err(Error::InvalidPacket);
+        }
+
+        let sequence = u32::from_be_bytes(
+            data[1..HEADER_SIZE]
+                .try_into()
+                .expect("header has a fixed size"),
+        );
+        let payload = data[HEADER_SIZE..].to_vec();
+
+        match data[0] {
+            RELIABLE => Ok(Self::Reliable { sequence, payload }),
+            UNRELIABLE => Ok(Self::Unreliable { sequence, payload }),
+            ACK if payload.is_empty() => Ok(Self::Ack { sequence }),
+            _ => Err(Error::InvalidPacket),
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// A request sent from an admin to the server's remote console.
+///
+/// The console listens on its own TCP port, and speaks the same length-prefixed frames as the game
+/// but always as JSON. Every connection has to log in before anything else.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RconRequest {
+    Login { password: String },
+    Command(Command),
+}
+
+/// Something an admin can have the server do.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Command {
+    /// Describes the server and everyone on it.
+    Status,
+    /// Removes a player from the match.
+    Kick { id: usize },
+    /// Keeps a player from joining again, for a number of seconds or for good, removing them
+    /// from the match if they are in it.
+    Ban {
+        target: BanTarget,
+        duration: Option<u64>,
+        reason: Option<String>,
+    },
+    /// Shows a message to every player.
+    Say { text: String },
+    /// Switches to another map, restarting the round.
+    ChangeLevel { map: String },
+    /// Puts everyone back at their spawn and starts the round over.
+    RestartRound,
+    /// Freezes or unfreezes the match.
+    Pause { paused: bool },
+    /// Reads a server variable.
+    Get { name: String },
+    /// Changes a server variable.
+    Set { name: String, value: String },
+}
+
+/// Who a ban is for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BanTarget {
+    /// A player on the server, by both their address and their name.
+    Player(usize),
+    Address(IpAddr),
+    /// A name, whoever joins with it. Players pick their own names and nothing ties one to a
+    /// person, so this only keeps someone out until they pick another.
+    Name(String),
+}
+
+/// The server's answer to a request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RconResponse {
+    /// The password was right, and commands may follow.
+    LoggedIn,
+    Status(Status),
+    Value {
+        name: String,
+        value: String,
+    },
+    /// The command was carried out.
+    Done,
+    /// The request could not be carried out, for the given reason.
+    Error(String),
+}
+
+/// A description of the server and everyone on it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Status {
+    pub hostname: String,
+    pub map: String,
+    /// Where the match is at, such as in the lobby or live.
+    pub phase: String,
+    pub paused: bool,
+    pub tick: u64,
+    pub players: Vec<PlayerStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerStatus {
+    pub id: usize,
+    pub name: String,
+    /// Where the player is connecting from, unless they are away.
+    pub address: Option<String>,
+    pub health: f64,
+    pub kills: u32,
+    pub deaths: u32,
+}
+
+/// Parses a duration such as `90s`, `30m`, `12h` or `7d` into seconds.
+pub fn parse_duration(text: &str) -> Result<u64, Error> {
+    let invalid = || Error::InvalidDuration(text.to_string());
+
+    let unit = match text.chars().last().ok_or_else(invalid)? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+
+    let count: u64 = text[..text.len() - 1].parse().map_err(|_| invalid())?;
+
+    count.checked_mul(unit).ok_or_else(invalid)
+}
+
+impl fmt::Display for Status {
+    /// Lays the status out as a table of players under a summary of the match.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} on {}", self.hostname, self.map)?;
+        writeln!(
+            f,
+            "{}{}, tick {}",
+            self.phase,
+            if self.paused { " (paused)" } else { "" },
+            self.tick
+        )?;
+        writeln!(f)?;
+
+        if self.players.is_empty() {
+            return writeln!(f, "No players");
+        }
+
+        writeln!(
+            f,
+            "{:>4}  {:<16}  {:<40}  {:>6}  {:>5}  {:>6}",
+            "ID", "NAME", "ADDRESS", "HEALTH", "KILLS", "DEATHS"
+        )?;
+        for player in &self.players {
+            writeln!(
+                f,
+                "{:>4}  {:<16}  {:<40}  {:>6.0}  {:>5}  {:>6}",
+                player.id,
+                player.name,
+                player.address.as_deref().unwrap_or("away"),
+                player.health,
+                player.kills,
+                player.deaths
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_unit() {
+        assert_eq!(parse_duration("90s").unwrap(), 90);
+        assert_eq!(parse_duration("30m").unwrap(), 30 * 60);
+        assert_eq!(parse_duration("12h").unwrap(), 12 * 60 * 60);
+        assert_eq!(parse_duration("7d").unwrap(), 7 * 24 * 60 * 60);
+        assert_eq!(parse_duration("0s").unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for text in ["", "s", "90", "1w", "-5m", "1.5h", "h1", "5 m", "5é"] {
+            assert!(
+                matches!(parse_duration(text), Err(Error::InvalidDuration(_))),
+                "{text:?} parsed"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        assert!(parse_duration(&format!("{}d", u64::MAX / 60)).is_err());
+        assert_eq!(parse_duration(&format!("{}s", u64::MAX)).unwrap(), u64::MAX);
+    }
+}
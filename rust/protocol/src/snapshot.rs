@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+
+use crate::position::Position;
+use crate::Error;
+
+/// The state of every connected player at a given tick.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The tick this snapshot was taken at, increasing by one every tick.
+    pub tick: u64,
+    pub players: Vec<PlayerState>,
+}
+
+/// The state of a single player.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerState {
+    pub id: usize,
+    pub health: f64,
+    /// The durability left on the player's armor.
+    pub armor: f64,
+    pub alive: bool,
+    pub position: Position,
+}
+
+/// The changes between a baseline snapshot and a newer one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Delta {
+    pub tick: u64,
+    /// The tick of the snapshot these changes apply to.
+    pub baseline: u64,
+    /// Players that joined or changed since the baseline.
+    pub players: Vec<PlayerDelta>,
+    /// Players that left since the baseline.
+    pub removed: Vec<usize>,
+}
+
+/// The fields of a player that changed since the baseline.
+///
+/// Players that are not part of the baseline have every field set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerDelta {
+    pub id: usize,
+    pub health: Option<f64>,
+    pub armor: Option<f64>,
+    pub alive: Option<bool>,
+    pub position: Option<Position>,
+}
+
+impl Snapshot {
+    /// Describes this snapshot as a set of changes to `baseline`.
+    pub fn delta(&self, baseline: &Snapshot) -> Delta {
+        let players = self
+            .players
+            .iter()
+            .filter_map(|player| {
+                let old = baseline.player(player.id);
+
+                let delta = PlayerDelta {
+                    id: player.id,
+                    health: changed(old.map(|p| p.health), player.health),
+                    armor: changed(old.map(|p| p.armor), player.armor),
+                    alive: changed(old.map(|p| p.alive), player.alive),
+                    position: changed(old.map(|p| p.position), player.position),
+                };
+
+                let unchanged = delta.health.is_none()
+                    && delta.armor.is_none()
+                    && delta.alive.is_none()
+                    && delta.position.is_none();
+
+                (!unchanged).then_some(delta)
+            })
+            .collect();
+
+        let removed = baseline
+            .players
+            .iter()
+            .filter(|player| self.player(player.id).is_none())
+            .map(|player| player.id)
+            .collect();
+
+        Delta {
+            tick: self.tick,
+            baseline: baseline.tick,
+            players,
+            removed,
+        }
+    }
+
+    /// Rebuilds the snapshot `delta` describes, using this snapshot as its baseline.
+    pub fn apply(&self, delta: &Delta) -> Result<Snapshot, Error> {
+        if delta.baseline != self.tick {
+            return Err(Error::InvalidDelta(self.tick));
+        }
+
+        let mut players: Vec<_> = self
+            .players
+            .iter()
+            .filter(|player| !delta.removed.contains(&player.id))
+            .cloned()
+            .collect();
+
+        for changes in &delta.players {
+            match players.iter_mut().find(|player| player.id == changes.id) {
+                Some(player) => {
+                    player.health = changes.health.unwrap_or(player.health);
+                    player.armor = changes.armor.unwrap_or(player.armor);
+                    player.alive = changes.alive.unwrap_or(player.alive);
+                    player.position = changes.position.unwrap_or(player.position);
+                }
+                None => players.push(PlayerState {
+                    id: changes.id,
+                    health: changes.health.ok_or(Error::InvalidDelta(self.tick))?,
+                    armor: changes.armor.ok_or(Error::InvalidDelta(self.tick))?,
+                    alive: changes.alive.ok_or(Error::InvalidDelta(self.tick))?,
+                    position: changes.position.ok_or(Error::InvalidDelta(self.tick))?,
+                }),
+            }
+        }
+
+        Ok(Snapshot {
+            tick: delta.tick,
+            players,
+        })
+    }
+
+    pub fn player(&self, id: usize) -> Option<&PlayerState> {
+        self.players.iter().find(|player| player.id == id)
+    }
+}
+
+/// Returns `new` if it differs from `old`.
+fn changed<T: Copy + PartialEq>(old: Option<T>, new: T) -> Option<T> {
+    (old != Some(new)).then_some(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{self, Encoding};
+
+    fn player(id: usize, health: f64, x: f64) -> PlayerState {
+        PlayerState {
+            id,
+            health,
+            armor: 50.0,
+            alive: health > 0.0,
+            position: Position::new(x, 0.0, 0.0),
+        }
+    }
+
+    fn baseline() -> Snapshot {
+        Snapshot {
+            tick: 10,
+            players: vec![
+                player(0, 100.0, 0.0),
+                player(1, 100.0, 1.0),
+                player(2, 100.0, 2.0),
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trips_changes_joins_and_leaves() {
+        let baseline = baseline();
+        // Player 0 moved, player 1 died, player 2 left and player 3 joined.
+        let snapshot = Snapshot {
+            tick: 12,
+            players: vec![
+                player(0, 100.0, 0.5),
+                player(1, 0.0, 1.0),
+                player(3, 100.0, 3.0),
+            ],
+        };
+
+        let delta = snapshot.delta(&baseline);
+        assert_eq!(delta.baseline, 10);
+        assert_eq!(delta.removed, [2]);
+        assert_eq!(
+            delta.players,
+            [
+                PlayerDelta {
+                    id: 0,
+                    health: None,
+                    armor: None,
+                    alive: None,
+                    position: Some(Position::new(0.5, 0.0, 0.0)),
+                },
+                PlayerDelta {
+                    id: 1,
+                    health: Some(0.0),
+                    armor: None,
+                    alive: Some(false),
+                    position: None,
+                },
+                PlayerDelta {
+                    id: 3,
+                    health: Some(100.0),
+                    armor: Some(50.0),
+                    alive: Some(true),
+                    position: Some(Position::new(3.0, 0.0, 0.0)),
+                },
+            ]
+        );
+
+        assert_eq!(baseline.apply(&delta).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn round_trips_through_both_encodings() {
+        let baseline = baseline();
+        let snapshot = Snapshot {
+            tick: 11,
+            players: vec![player(0, 75.0, 0.0), player(2, 100.0, 4.0)],
+        };
+        let delta = snapshot.delta(&baseline);
+
+        for encoding in [Encoding::Json, Encoding::Bincode] {
+            let payload = message::encode(&delta, encoding).unwrap();
+            let decoded: Delta = message::decode(&payload, encoding).unwrap();
+
+            assert_eq!(baseline.apply(&decoded).unwrap(), snapshot);
+        }
+    }
+
+    #[test]
+    fn unchanged_snapshot_has_an_empty_delta() {
+        let baseline = baseline();
+        let snapshot = Snapshot {
+            tick: 11,
+            ..baseline.clone()
+        };
+
+        let delta = snapshot.delta(&baseline);
+        assert!(delta.players.is_empty());
+        assert!(delta.removed.is_empty());
+
+        assert_eq!(baseline.apply(&delta).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn rejects_a_delta_against_another_baseline() {
+        let baseline = baseline();
+        let snapshot = Snapshot {
+            tick: 11,
+            ..baseline.clone()
+        };
+        let delta = snapshot.delta(&baseline);
+
+        let other = Snapshot {
+            tick: 9,
+            ..baseline
+        };
+        assert!(matches!(other.apply(&delta), Err(Error::InvalidDelta(9))));
+    }
+
+    #[test]
+    fn rejects_a_new_player_with_missing_fields() {
+        let delta = Delta {
+            tick: 11,
+            baseline: 10,
+            players: vec![PlayerDelta {
+                id: 5,
+                health: Some(100.0),
+                armor: None,
+                alive: Some(true),
+                position: Some(Position::new(0.0, 0.0, 0.0)),
+            }],
+            removed: Vec::new(),
+        };
+
+        assert!(matches!(
+            baseline().apply(&delta),
+            Err(Error::InvalidDelta(10))
+        ));
+    }
+}
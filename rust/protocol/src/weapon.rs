@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+/// What a shot to the head does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HeadshotAction {
+    Kill,
+    DoubleDamage,
+}
+
+/// The stats of a weapon, which the server uses to decide how much damage a hit deals.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weapon {
+    pub max_damage: f64,
+    pub min_damage: f64,
+
+    pub max_range: f64,
+    pub falloff_start: f64,
+    pub falloff_end: f64,
+
+    /// The time between two shots, in seconds.
+    pub fire_interval: f64,
+    pub max_ammo: u32,
+    /// The time it takes to reload, in seconds.
+    pub reload_time: f64,
+
+    pub on_headshot: HeadshotAction,
+}
+
+pub const AK47: Weapon = Weapon {
+    max_damage: 25.0,
+    min_damage: 10.0,
+
+    max_range: 500.0,
+    falloff_start: 40.0,
+    falloff_end: 300.0,
+
+    fire_interval: 0.05,
+    max_ammo: 30,
+    reload_time: 3.0,
+
+    on_headshot: HeadshotAction::Kill,
+};
+
+impl Weapon {
+    /// The damage a body shot deals at the given distance.
+    pub fn damage_at(&self, distance: f64) -> f64 {
+        // Control the damage falloff based on the distance.
+        if distance <= self.falloff_start {
+            return self.max_damage;
+        } else if distance >= self.falloff_end {
+            return self.min_damage;
+        }
+
+        let n = (distance - self.falloff_start) / (self.falloff_end - self.falloff_start);
+
+        n * self.min_damage + (1.0 - n) * self.max_damage
+    }
+
+    /// The damage a headshot deals given what a body shot would, or `None` if it kills outright.
+    pub fn headshot_damage(&self, damage: f64) -> Option<f64> {
+        match self.on_headshot {
+            HeadshotAction::Kill => None,
+            HeadshotAction::DoubleDamage => Some(damage * 2.0),
+        }
+    }
+}
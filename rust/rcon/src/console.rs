@@ -0,0 +1,62 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use protocol::frame::{self, FrameDecoder};
+use protocol::message::{self, Encoding};
+use protocol::rcon::{Command, RconRequest, RconResponse};
+
+use crate::Error;
+
+/// How long to wait for the server to connect or answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A logged in connection to a server's remote console.
+pub struct Console {
+    socket: TcpStream,
+    decoder: FrameDecoder,
+}
+
+impl Console {
+    /// Connects to the console at `address` and logs in.
+    pub fn connect(address: SocketAddr, password: &str) -> Result<Self, Error> {
+        let socket = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        socket.set_read_timeout(Some(TIMEOUT))?;
+
+        let mut console = Self {
+            socket,
+            decoder: FrameDecoder::new(),
+        };
+
+        let login = RconRequest::Login {
+            password: password.to_string(),
+        };
+        match console.request(&login)? {
+            RconResponse::LoggedIn => Ok(console),
+            RconResponse::Error(reason) => Err(Error::LoginFailed(reason)),
+            _ => Err(Error::UnexpectedResponse),
+        }
+    }
+
+    /// Has the server carry out `command`, and waits for its answer.
+    pub fn execute(&mut self, command: Command) -> Result<RconResponse, Error> {
+        self.request(&RconRequest::Command(command))
+    }
+
+    fn request(&mut self, request: &RconRequest) -> Result<RconResponse, Error> {
+        let data = message::encode(request, Encoding::Json)?;
+        self.socket.write_all(&frame::encode(&data)?)?;
+
+        let mut buffer = [0; 4096];
+        loop {
+            if let Some(frame) = self.decoder.next_frame()? {
+                return Ok(message::decode(&frame, Encoding::Json)?);
+            }
+
+            match self.socket.read(&mut buffer)? {
+                0 => return Err(Error::HungUp),
+                read => self.decoder.extend(&buffer[..read]),
+            }
+        }
+    }
+}
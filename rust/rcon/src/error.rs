@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Protocol error: {0}")]
+    Protocol(#[from] protocol::Error),
+    #[error("Failed to log in: {0}")]
+    LoginFailed(String),
+    #[error("The server hung up")]
+    HungUp,
+    #[error("The server sent an unexpected response")]
+    UnexpectedResponse,
+}
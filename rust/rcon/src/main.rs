@@ -0,0 +1,155 @@
+use std::net::{IpAddr, SocketAddr};
+use std::process::ExitCode;
+
+use clap::{ArgGroup, Parser, Subcommand};
+use protocol::rcon::{parse_duration, BanTarget, Command, RconResponse};
+
+use console::Console;
+use error::Error;
+
+mod console;
+mod error;
+
+/// Manages a running game server over its remote console.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// The address the server's remote console listens on.
+    #[arg(short, long, default_value = "127.0.0.1:7513")]
+    address: SocketAddr,
+
+    /// The password to log in to the remote console with, asked for if left out.
+    ///
+    /// Anything on the command line can be read by other users of the machine, so prefer the
+    /// environment variable or the prompt.
+    #[arg(short, long, env = "RCON_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Debug, Subcommand)]
+enum Action {
+    /// Describes the server and everyone on it.
+    Status,
+    /// Removes a player from the match.
+    Kick { id: usize },
+    /// Keeps a player, an address or a name from joining, removing them from the match.
+    #[command(group(ArgGroup::new("target").required(true).args(["id", "address", "name"])))]
+    Ban {
+        /// The id of a player on the server, to ban by both their address and their name.
+        id: Option<usize>,
+
+        /// An address to ban, whether or not anyone is playing from it.
+        #[arg(long)]
+        address: Option<IpAddr>,
+
+        /// A name to ban, whether or not anyone is playing under it. Players pick their own
+        /// names, so this only keeps someone out until they pick another.
+        #[arg(long)]
+        name: Option<String>,
+
+        /// How long the ban lasts, such as 30m, 12h or 7d. Leave it out to ban for good.
+        #[arg(short, long, value_parser = parse_duration)]
+        duration: Option<u64>,
+
+        /// Why the player is banned, which they are told when turned away.
+        #[arg(short, long)]
+        reason: Option<String>,
+    },
+    /// Shows a message to every player.
+    Say {
+        #[arg(required = true)]
+        text: Vec<String>,
+    },
+    /// Switches to another map, restarting the round.
+    Changelevel { map: String },
+    /// Puts everyone back at their spawn and starts the round over.
+    Restart,
+    /// Freezes the match.
+    Pause,
+    /// Lets a paused match carry on.
+    Unpause,
+    /// Reads a server variable.
+    Get { name: String },
+    /// Changes a server variable.
+    Set { name: String, value: String },
+}
+
+impl From<Action> for Command {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Status => Command::Status,
+            Action::Kick { id } => Command::Kick { id },
+            Action::Ban {
+                id,
+                address,
+                name,
+                duration,
+                reason,
+            } => {
+                let target = match (id, address, name) {
+                    (Some(id), _, _) => BanTarget::Player(id),
+                    (_, Some(address), _) => BanTarget::Address(address),
+                    (_, _, Some(name)) => BanTarget::Name(name),
+                    _ => unreachable!("clap requires one target"),
+                };
+
+                Command::Ban {
+                    target,
+                    duration,
+                    reason,
+                }
+            }
+            Action::Say { text } => Command::Say {
+                text: text.join(" "),
+            },
+            Action::Changelevel { map } => Command::ChangeLevel { map },
+            Action::Restart => Command::RestartRound,
+            Action::Pause => Command::Pause { paused: true },
+            Action::Unpause => Command::Pause { paused: false },
+            Action::Get { name } => Command::Get { name },
+            Action::Set { name, value } => Command::Set { name, value },
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let password = match args.password {
+        Some(password) => Ok(password),
+        None => rpassword::prompt_password("Password: ").map_err(Error::from),
+    };
+
+    let result = password
+        .and_then(|password| Console::connect(args.address, &password))
+        .and_then(|mut console| console.execute(args.action.into()));
+
+    match result {
+        Ok(RconResponse::Error(error)) => {
+            eprintln!("{error}");
+
+            ExitCode::FAILURE
+        }
+        Ok(response) => {
+            print(response);
+
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{error}");
+
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print(response: RconResponse) {
+    match response {
+        RconResponse::Status(status) => print!("{status}"),
+        RconResponse::Value { name, value } => println!("{name} = {value:?}"),
+        RconResponse::LoggedIn | RconResponse::Done | RconResponse::Error(_) => {}
+    }
+}
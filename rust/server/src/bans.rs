@@ -0,0 +1,314 @@
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use protocol::message::Rejection;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{SaveError, StartupError};
+
+/// Keeps an address, a name, or both from joining the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ban {
+    pub ip: Option<IpAddr>,
+    /// The name the player joins with.
+    ///
+    /// Players pick their own names and nothing ties one to a person, so a ban by name only keeps
+    /// someone out until they think of another.
+    pub name: Option<String>,
+    /// Why the player was banned, which they are told when turned away.
+    pub reason: Option<String>,
+    /// When the ban runs out, in seconds since the Unix epoch, or never if left out.
+    pub expires: Option<u64>,
+}
+
+impl Ban {
+    /// Whether the ban applies to a player with the given address or name.
+    pub fn covers(&self, ip: Option<IpAddr>, name: Option<&str>) -> bool {
+        let ip = ip.is_some_and(|ip| self.ip == Some(ip.to_canonical()));
+        let name = name.is_some_and(|name| self.name.as_deref() == Some(name));
+
+        ip || name
+    }
+
+    fn has_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// What to tell a player the ban turns away.
+    pub fn rejection(&self) -> Rejection {
+        Rejection::Banned {
+            reason: self.reason.clone(),
+            expires: self.expires,
+        }
+    }
+}
+
+impl fmt::Display for Ban {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.name, &self.ip) {
+            (Some(name), Some(ip)) => write!(f, "{name} ({ip})")?,
+            (Some(name), None) => write!(f, "{name}")?,
+            (None, Some(ip)) => write!(f, "{ip}")?,
+            (None, None) => write!(f, "nobody")?,
+        }
+
+        match self.expires {
+            Some(expires) => write!(f, " for {}s", expires.saturating_sub(now()))?,
+            None => write!(f, " for good")?,
+        }
+
+        match &self.reason {
+            Some(reason) => write!(f, ": {reason}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The ban list, shared by the game loop and the listeners that turn banned addresses away.
+pub type Bans = Arc<RwLock<BanList>>;
+
+/// What to tell connections from `ip` as soon as they are accepted, if it is banned.
+pub fn rejection(bans: &Bans, ip: IpAddr) -> Option<Rejection> {
+    bans.read()
+        .expect("the ban list is never poisoned")
+        .find(Some(ip), None)
+        .map(Ban::rejection)
+}
+
+/// The bans as they are kept on disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct File {
+    ban: Vec<Ban>,
+}
+
+/// Every ban in effect, kept in a file so they outlive the server.
+#[derive(Debug)]
+pub struct BanList {
+    path: PathBuf,
+    bans: Vec<Ban>,
+    /// How many times the list has changed.
+    revision: u64,
+    /// The revision last written to the file, so an older copy is never written over a newer one.
+    saved: Arc<Mutex<u64>>,
+}
+
+/// A copy of the ban list, to be written to its file away from the game loop.
+#[derive(Debug)]
+pub struct Save {
+    path: PathBuf,
+    bans: Vec<Ban>,
+    revision: u64,
+    saved: Arc<Mutex<u64>>,
+}
+
+impl BanList {
+    /// Reads the bans from `path`, starting out with none if the file does not exist yet.
+    pub fn load(path: &Path) -> Result<Self, StartupError> {
+        let bans = match fs::read_to_string(path) {
+            Ok(text) => {
+                toml::from_str::<File>(&text)
+                    .map_err(StartupError::Bans)?
+                    .ban
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            bans,
+            revision: 0,
+            saved: Arc::default(),
+        })
+    }
+
+    /// Finds a ban in effect for a player with the given address or name.
+    pub fn find(&self, ip: Option<IpAddr>, name: Option<&str>) -> Option<&Ban> {
+        let now = now();
+
+        self.bans
+            .iter()
+            .find(|ban| !ban.has_expired(now) && ban.covers(ip, name))
+    }
+
+    /// Adds a ban, returning the list as it has to be written back to its file.
+    pub fn add(&mut self, ban: Ban) -> Save {
+        let now = now();
+        self.bans.retain(|ban| !ban.has_expired(now));
+        self.bans.push(ban);
+        self.revision += 1;
+
+        Save {
+            path: self.path.clone(),
+            bans: self.bans.clone(),
+            revision: self.revision,
+            saved: self.saved.clone(),
+        }
+    }
+}
+
+impl Save {
+    /// Writes the list to its file, which blocks, unless a newer one was written already.
+    pub fn write(self) -> Result<(), SaveError> {
+        let mut saved = self
+            .saved
+            .lock()
+            .expect("the ban list file is never poisoned");
+        if *saved >= self.revision {
+            return Ok(());
+        }
+
+        let text = toml::to_string(&File { ban: self.bans })?;
+
+        // Write it next to the old list first, so a crash halfway through never loses it.
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, text)?;
+        fs::rename(temporary, &self.path)?;
+
+        *saved = self.revision;
+
+        Ok(())
+    }
+}
+
+/// The current time in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    /// A ban list file of its own for each test, removed once it is done with.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("bans-{}-{name}.toml", process::id()));
+            let _ = fs::remove_file(&path);
+
+            Self(path)
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn ban(ip: Option<&str>, name: Option<&str>, expires: Option<u64>) -> Ban {
+        Ban {
+            ip: ip.map(|ip| ip.parse().unwrap()),
+            name: name.map(str::to_string),
+            reason: Some("cheating".to_string()),
+            expires,
+        }
+    }
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        Some(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn starts_empty_without_a_file() {
+        let scratch = Scratch::new("missing");
+
+        let bans = BanList::load(&scratch.0).unwrap();
+        assert!(bans.find(ip("10.0.0.1"), Some("player")).is_none());
+        assert!(!scratch.0.exists());
+    }
+
+    #[test]
+    fn keeps_bans_across_loads() {
+        let scratch = Scratch::new("persist");
+
+        let mut bans = BanList::load(&scratch.0).unwrap();
+        bans.add(ban(Some("10.0.0.1"), None, None)).write().unwrap();
+        bans.add(ban(None, Some("griefer"), Some(now() + 3600)))
+            .write()
+            .unwrap();
+
+        let bans = BanList::load(&scratch.0).unwrap();
+        let found = bans.find(ip("10.0.0.1"), Some("player")).unwrap();
+        assert_eq!(found.reason.as_deref(), Some("cheating"));
+        assert_eq!(
+            found.rejection(),
+            Rejection::Banned {
+                reason: Some("cheating".to_string()),
+                expires: None,
+            }
+        );
+
+        assert!(bans.find(ip("10.0.0.2"), Some("griefer")).is_some());
+        assert!(bans.find(ip("10.0.0.2"), Some("player")).is_none());
+        assert!(bans.find(None, Some("player")).is_none());
+    }
+
+    #[test]
+    fn matches_ipv4_clients_on_ipv6_sockets() {
+        let scratch = Scratch::new("mapped");
+
+        let mut bans = BanList::load(&scratch.0).unwrap();
+        bans.add(ban(Some("10.0.0.1"), None, None)).write().unwrap();
+
+        assert!(bans.find(ip("::ffff:10.0.0.1"), None).is_some());
+        assert!(bans.find(ip("::1"), None).is_none());
+    }
+
+    #[test]
+    fn forgets_bans_that_ran_out() {
+        let scratch = Scratch::new("expired");
+
+        let mut bans = BanList::load(&scratch.0).unwrap();
+        bans.add(ban(Some("10.0.0.1"), None, Some(now() - 1)))
+            .write()
+            .unwrap();
+        assert!(bans.find(ip("10.0.0.1"), None).is_none());
+
+        // The next ban clears it out of the file.
+        bans.add(ban(Some("10.0.0.2"), None, None)).write().unwrap();
+        let text = fs::read_to_string(&scratch.0).unwrap();
+        assert!(!text.contains("10.0.0.1"), "{text}");
+        assert!(text.contains("10.0.0.2"), "{text}");
+    }
+
+    #[test]
+    fn never_writes_an_older_list_over_a_newer_one() {
+        let scratch = Scratch::new("revision");
+
+        let mut bans = BanList::load(&scratch.0).unwrap();
+        let older = bans.add(ban(Some("10.0.0.1"), None, None));
+        let newer = bans.add(ban(Some("10.0.0.2"), None, None));
+
+        newer.write().unwrap();
+        older.write().unwrap();
+
+        let bans = BanList::load(&scratch.0).unwrap();
+        assert!(bans.find(ip("10.0.0.2"), None).is_some());
+    }
+
+    #[test]
+    fn rejects_a_malformed_file() {
+        let scratch = Scratch::new("malformed");
+        fs::write(&scratch.0, "[[ban]]\naddress = \"10.0.0.1\"\n").unwrap();
+
+        assert!(matches!(
+            BanList::load(&scratch.0),
+            Err(StartupError::Bans(_))
+        ));
+    }
+}
@@ -0,0 +1,53 @@
+use protocol::frame::{self, MAX_FRAME_SIZE};
+use protocol::message::{self, Encoding};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::Error;
+
+/// Reads a single length-prefixed frame.
+///
+/// See [`protocol::frame`] for the layout of a frame.
+/// Partial reads are handled by waiting until the whole frame has arrived.
+pub async fn read_frame<R>(reader: &mut R) -> Result<Vec<u8>, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    let size = reader.read_u32().await? as usize;
+    if size > MAX_FRAME_SIZE {
+        return Err(protocol::Error::FrameTooLarge(size).into());
+    }
+
+    let mut frame = vec![0; size];
+    reader.read_exact(&mut frame).await?;
+
+    Ok(frame)
+}
+
+/// Writes `data` as a single length-prefixed frame.
+pub async fn write_frame<W>(writer: &mut W, data: &[u8]) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin,
+{
+    let frame = frame::encode(data)?;
+
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+
+    Ok(())
+}
+
+/// Serializes a message and writes it as a single frame.
+pub async fn write_message<W, T>(
+    writer: &mut W,
+    message: &T,
+    encoding: Encoding,
+) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let data = message::encode(message, encoding)?;
+
+    write_frame(writer, &data).await
+}
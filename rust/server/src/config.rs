@@ -0,0 +1,517 @@
+use std::fmt::Display;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use protocol::frame::MAX_FRAME_SIZE;
+use protocol::message::{Encoding, GameMode};
+use protocol::packet::MAX_PAYLOAD_SIZE;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::error::StartupError;
+use crate::player::{Authority, Response};
+
+/// The messages a second a client may send on top of an input and a snapshot acknowledgement
+/// every tick, for its shots, chat and pongs.
+const MESSAGE_HEADROOM: u32 = 32;
+
+/// How clients talk to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// Everything over a single TCP stream, which is simple to inspect and debug.
+    Tcp,
+    /// Datagrams with a reliable channel for events and a latest-wins one for snapshots.
+    Udp,
+}
+
+/// Everything the server can be set up with, as read from its config file.
+///
+/// Keys left out of the file keep their defaults, and command line flags override both.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The name of the server, shown to players when they join.
+    pub hostname: String,
+    /// The addresses to listen on, which all lead to the same lobby.
+    ///
+    /// An unspecified IPv6 address such as `[::]:7512` accepts IPv4 clients too.
+    pub bind: Vec<SocketAddr>,
+    pub transport: Transport,
+    /// The number of simulation ticks per second, usually 64 or 128.
+    pub tick_rate: u32,
+
+    /// The number of ready players needed to start the match.
+    pub min_players: usize,
+    /// The number of players a match has room for.
+    pub max_players: usize,
+    /// The password players need to join with, if any.
+    pub password: Option<String>,
+    /// The file banned players are kept in, which is created on the first ban.
+    pub bans: PathBuf,
+
+    /// The map the server starts on.
+    pub map: String,
+    #[serde(deserialize_with = "parse")]
+    pub mode: GameMode,
+    pub round: Round,
+    pub rcon: Rcon,
+
+    /// How long the countdown before the match starts is, in seconds.
+    pub countdown: u64,
+    /// How long a player who lost their connection keeps their place, in seconds.
+    pub grace_period: u64,
+
+    pub movement_authority: Authority,
+    pub movement_violation: Response,
+
+    /// The number of messages a connection may send a second, which has to leave room for an
+    /// input and a snapshot acknowledgement every tick.
+    pub max_messages_per_second: u32,
+    pub max_bytes_per_second: u32,
+    /// The size of the largest message a connection may send, in bytes.
+    pub max_message_size: usize,
+
+    /// The encoding to speak with clients that support it.
+    #[serde(deserialize_with = "parse")]
+    pub encoding: Encoding,
+}
+
+/// How a round plays out once the match has started.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Round {
+    /// How long a round lasts, in seconds, or 0 to keep it going until the server stops.
+    pub time_limit: u64,
+}
+
+/// The remote console admins manage the server with.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rcon {
+    /// The addresses to listen on, which are best kept out of reach of players.
+    pub bind: Vec<SocketAddr>,
+    /// The password admins log in with. The console is off without one.
+    pub password: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            hostname: "Game server".to_string(),
+            bind: vec![(Ipv4Addr::UNSPECIFIED, 7512).into()],
+            transport: Transport::Tcp,
+            tick_rate: 64,
+
+            min_players: 1,
+            max_players: 10,
+            password: None,
+            bans: PathBuf::from("bans.toml"),
+
+            map: "arena".to_string(),
+            mode: GameMode::Deathmatch,
+            round: Round::default(),
+            rcon: Rcon::default(),
+
+            countdown: 5,
+            grace_period: 60,
+
+            movement_authority: Authority::Server,
+            movement_violation: Response::Correct,
+
+            max_messages_per_second: 256,
+            max_bytes_per_second: 65536,
+            max_message_size: 4096,
+
+            encoding: Encoding::Bincode,
+        }
+    }
+}
+
+impl Default for Round {
+    fn default() -> Self {
+        Self { time_limit: 600 }
+    }
+}
+
+impl Default for Rcon {
+    fn default() -> Self {
+        Self {
+            bind: vec![(Ipv4Addr::LOCALHOST, 7513).into()],
+            password: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, StartupError> {
+        let text = fs::read_to_string(path)?;
+
+        Ok(toml::from_str(&text)?)
+    }
+
+    /// Checks that the settings make sense together, describing everything that is wrong.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.hostname.trim().is_empty() {
+            errors.push("hostname cannot be empty".to_string());
+        }
+
+        if self.bind.is_empty() {
+            errors.push("bind needs at least one address to listen on".to_string());
+        }
+
+        for (i, address) in self.bind.iter().enumerate() {
+            for other in &self.bind[..i] {
+                if let Some(error) = conflict("bind", other, "bind", address) {
+                    errors.push(error);
+                }
+            }
+        }
+
+        if self.rcon.password.is_some() {
+            if self.rcon.bind.is_empty() {
+                errors.push("rcon.bind needs at least one address to listen on".to_string());
+            }
+
+            for (i, address) in self.rcon.bind.iter().enumerate() {
+                for other in &self.rcon.bind[..i] {
+                    if let Some(error) = conflict("rcon.bind", other, "rcon.bind", address) {
+                        errors.push(error);
+                    }
+                }
+
+                // Players and admins may share a port when players come in over UDP.
+                if self.transport != Transport::Tcp {
+                    continue;
+                }
+
+                for other in &self.bind {
+                    if let Some(error) = conflict("bind", other, "rcon.bind", address) {
+                        errors.push(error);
+                    }
+                }
+            }
+        }
+
+        if !(1..=1000).contains(&self.tick_rate) {
+            errors.push(format!(
+                "tick_rate must be between 1 and 1000, not {}",
+                self.tick_rate
+            ));
+        }
+
+        if self.min_players == 0 {
+            errors.push("min_players must be at least 1".to_string());
+        }
+
+        if self.min_players > self.max_players {
+            errors.push(format!(
+                "min_players ({}) cannot be more than max_players ({})",
+                self.min_players, self.max_players
+            ));
+        }
+
+        if self.password.as_deref() == Some("") {
+            errors.push("password cannot be empty, leave it out to let anyone join".to_string());
+        }
+
+        if self.rcon.password.as_deref() == Some("") {
+            errors.push("rcon.password cannot be empty, leave it out to turn it off".to_string());
+        }
+
+        if self.map.trim().is_empty() {
+            errors.push("map cannot be empty".to_string());
+        }
+
+        let needed = self
+            .tick_rate
+            .saturating_mul(2)
+            .saturating_add(MESSAGE_HEADROOM);
+        if self.max_messages_per_second < needed {
+            errors.push(format!(
+                "max_messages_per_second must be at least {needed} at a tick_rate of {}, not {}, \
+                 as clients send an input and acknowledge a snapshot every tick",
+                self.tick_rate, self.max_messages_per_second
+            ));
+        }
+
+        let largest = match self.transport {
+            Transport::Tcp => MAX_FRAME_SIZE,
+            Transport::Udp => MAX_PAYLOAD_SIZE,
+        };
+        if !(1..=largest).contains(&self.max_message_size) {
+            errors.push(format!(
+                "max_message_size must be between 1 and {largest} bytes, not {}",
+                self.max_message_size
+            ));
+        }
+
+        if (self.max_bytes_per_second as usize) < self.max_message_size {
+            errors.push(format!(
+                "max_bytes_per_second ({}) cannot be less than max_message_size ({})",
+                self.max_bytes_per_second, self.max_message_size
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Describes why two addresses cannot both be listened on, if they cannot.
+///
+/// IPv6 sockets take IPv4 clients too, so `[::]` takes up a port on every address of both, just
+/// like `0.0.0.0` does on every IPv4 address.
+fn conflict(key: &str, first: &SocketAddr, other_key: &str, second: &SocketAddr) -> Option<String> {
+    if first.port() != second.port() {
+        return None;
+    }
+
+    let (a, b) = (first.ip().to_canonical(), second.ip().to_canonical());
+    if a == b {
+        return Some(if key == other_key {
+            format!("{key} lists {second} more than once")
+        } else {
+            format!("{key} and {other_key} both list {second}")
+        });
+    }
+
+    let covers = |wildcard: IpAddr, other: IpAddr| {
+        wildcard.is_unspecified() && (wildcard.is_ipv6() || other.is_ipv4())
+    };
+    let wildcard = if covers(a, b) {
+        first
+    } else if covers(b, a) {
+        second
+    } else {
+        return None;
+    };
+
+    let listed = if key == other_key {
+        format!("{key} lists both {first} and {second}")
+    } else {
+        format!("{key} lists {first} and {other_key} lists {second}")
+    };
+
+    let family = if wildcard.is_ipv6() { "" } else { "IPv4 " };
+    Some(format!(
+        "{listed}, but {wildcard} already takes port {} on every {family}address",
+        first.port()
+    ))
+}
+
+/// Deserializes a value from the same string it is given as on the command line.
+fn parse<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addresses(addresses: &[&str]) -> Vec<SocketAddr> {
+        addresses
+            .iter()
+            .map(|address| address.parse().unwrap())
+            .collect()
+    }
+
+    fn with_bind(bind: &[&str]) -> Config {
+        Config {
+            bind: addresses(bind),
+            ..Config::default()
+        }
+    }
+
+    fn with_rcon(transport: Transport, bind: &[&str], rcon: &[&str]) -> Config {
+        Config {
+            bind: addresses(bind),
+            transport,
+            rcon: Rcon {
+                bind: addresses(rcon),
+                password: Some("secret".to_string()),
+            },
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(Config::default().validate(), Ok(()));
+        assert_eq!(
+            with_rcon(Transport::Tcp, &["0.0.0.0:7512"], &["127.0.0.1:7513"]).validate(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn reads_keys_left_out_as_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            hostname = "Friday night"
+            encoding = "json"
+
+            [rcon]
+            password = "secret"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.hostname, "Friday night");
+        assert_eq!(config.encoding, Encoding::Json);
+        assert_eq!(config.rcon.password.as_deref(), Some("secret"));
+        assert_eq!(config.rcon.bind, Rcon::default().bind);
+        assert_eq!(config.tick_rate, 64);
+
+        assert!(toml::from_str::<Config>("tickrate = 64").is_err());
+        assert!(toml::from_str::<Config>(r#"encoding = "xml""#).is_err());
+    }
+
+    #[test]
+    fn describes_every_error() {
+        let config = Config {
+            hostname: " ".to_string(),
+            tick_rate: 0,
+            min_players: 12,
+            password: Some(String::new()),
+            ..Config::default()
+        };
+
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.len(), 4, "{errors:?}");
+    }
+
+    #[test]
+    fn leaves_room_for_a_message_each_way_every_tick() {
+        let config = Config {
+            tick_rate: 128,
+            max_messages_per_second: 288,
+            ..Config::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+
+        let config = Config {
+            max_messages_per_second: 287,
+            ..config
+        };
+        assert_eq!(
+            config.validate().unwrap_err(),
+            [
+                "max_messages_per_second must be at least 288 at a tick_rate of 128, not 287, as \
+                 clients send an input and acknowledge a snapshot every tick"
+            ]
+        );
+    }
+
+    #[test]
+    fn limits_message_size_by_transport() {
+        let config = Config {
+            max_message_size: MAX_PAYLOAD_SIZE + 1,
+            max_bytes_per_second: u32::MAX,
+            ..Config::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+
+        let config = Config {
+            transport: Transport::Udp,
+            ..config
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_the_same_address_twice() {
+        let errors = with_bind(&["127.0.0.1:7512", "[::ffff:127.0.0.1]:7512"])
+            .validate()
+            .unwrap_err();
+
+        assert_eq!(
+            errors,
+            ["bind lists [::ffff:127.0.0.1]:7512 more than once"]
+        );
+    }
+
+    #[test]
+    fn rejects_addresses_taken_by_a_wildcard() {
+        let errors = with_bind(&["[::]:7512", "127.0.0.1:7512"])
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "bind lists both [::]:7512 and 127.0.0.1:7512, but [::]:7512 already takes port \
+              7512 on every address"
+            ]
+        );
+
+        let errors = with_bind(&["[::1]:7512", "[::]:7512"])
+            .validate()
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+
+        let errors = with_bind(&["127.0.0.1:7512", "0.0.0.0:7512"])
+            .validate()
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                "bind lists both 127.0.0.1:7512 and 0.0.0.0:7512, but 0.0.0.0:7512 already takes \
+              port 7512 on every IPv4 address"
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_addresses_that_do_not_overlap() {
+        let config = with_bind(&["0.0.0.0:7512", "[::1]:7512", "[::]:7514", "127.0.0.1:7515"]);
+
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn keeps_rcon_off_the_players_port_over_tcp() {
+        let config = with_rcon(Transport::Tcp, &["[::]:7512"], &["127.0.0.1:7512"]);
+        assert_eq!(
+            config.validate().unwrap_err(),
+            [
+                "bind lists [::]:7512 and rcon.bind lists 127.0.0.1:7512, but [::]:7512 already \
+              takes port 7512 on every address"
+            ]
+        );
+
+        let config = with_rcon(Transport::Tcp, &["127.0.0.1:7512"], &["127.0.0.1:7512"]);
+        assert_eq!(
+            config.validate().unwrap_err(),
+            ["bind and rcon.bind both list 127.0.0.1:7512"]
+        );
+
+        // UDP players and TCP admins can share a port.
+        let config = with_rcon(Transport::Udp, &["[::]:7512"], &["127.0.0.1:7512"]);
+        assert_eq!(config.validate(), Ok(()));
+
+        // Without a password, the console is off and its addresses are not used.
+        let config = Config {
+            rcon: Rcon {
+                password: None,
+                ..config.rcon
+            },
+            transport: Transport::Tcp,
+            ..config
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+}
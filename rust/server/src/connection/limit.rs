@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Instant;
+
+use log::warn;
+
+use crate::stats::{self, STATS};
+use crate::Error;
+
+/// How many messages over budget a connection may build up before it is disconnected.
+const MAX_STRIKES: f64 = 100.0;
+/// How many strikes a connection is forgiven per second.
+const STRIKE_RECOVERY: f64 = 10.0;
+
+/// How many wrong passwords an address may try in a row before it has to wait.
+const MAX_FAILED_ATTEMPTS: f64 = 5.0;
+/// How many failed attempts an address is forgiven per second.
+const ATTEMPT_RECOVERY: f64 = 1.0 / 12.0;
+
+/// How much a single connection may send.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub messages_per_second: f64,
+    pub bytes_per_second: f64,
+    /// The size of the largest message that will be parsed, in bytes.
+    pub max_message_size: usize,
+}
+
+/// A budget that fills up at a steady rate, up to a limit.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    /// How many tokens are added per second.
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket, which may be emptied in one go.
+    pub fn new(capacity: f64, rate: f64) -> Self {
+        Self {
+            capacity,
+            rate,
+            tokens: capacity,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Takes `amount` tokens if there are enough of them.
+    pub fn take(&mut self, amount: f64, now: Instant) -> bool {
+        self.refill(now);
+
+        if self.tokens < amount {
+            return false;
+        }
+
+        self.tokens -= amount;
+        true
+    }
+
+    /// Whether there is not even a single token left.
+    pub fn is_empty(&mut self, now: Instant) -> bool {
+        self.refill(now);
+
+        self.tokens < 1.0
+    }
+
+    /// Whether the bucket has filled back up, as if it was never touched.
+    pub fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+
+        self.tokens >= self.capacity
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+}
+
+/// The wrong passwords each address can still try before it has to wait.
+#[derive(Debug, Default)]
+pub struct Attempts(HashMap<IpAddr, TokenBucket>);
+
+impl Attempts {
+    /// Whether `ip` got the password wrong too often lately, and has to wait to try again.
+    pub fn is_locked_out(&mut self, ip: IpAddr, now: Instant) -> bool {
+        self.0
+            .get_mut(&ip.to_canonical())
+            .is_some_and(|attempts| attempts.is_empty(now))
+    }
+
+    /// Counts a wrong password against `ip`.
+    pub fn fail(&mut self, ip: IpAddr, now: Instant) {
+        // Only addresses that still have to earn back attempts are worth remembering.
+        self.0.retain(|_, attempts| !attempts.is_full(now));
+        self.0
+            .entry(ip.to_canonical())
+            .or_insert_with(|| TokenBucket::new(MAX_FAILED_ATTEMPTS, ATTEMPT_RECOVERY))
+            .take(1.0, now);
+    }
+}
+
+/// Whether `given` is the password, taking as long to tell however much of it is right, so
+/// guessing cannot be sped up by timing the answer.
+pub fn password_matches(given: &str, password: &str) -> bool {
+    let (given, password) = (given.as_bytes(), password.as_bytes());
+
+    let difference = password
+        .iter()
+        .enumerate()
+        .fold(given.len() ^ password.len(), |difference, (i, byte)| {
+            difference | usize::from(byte ^ given.get(i).copied().unwrap_or(0))
+        });
+
+    difference == 0
+}
+
+/// Keeps a connection to its budget, throttling it when it goes over and disconnecting it when
+/// it keeps at it.
+#[derive(Debug)]
+pub struct RateLimiter {
+    id: usize,
+    limits: Limits,
+
+    messages: TokenBucket,
+    bytes: TokenBucket,
+    strikes: TokenBucket,
+    /// Whether the last message was over budget, so only the start of a flood is logged.
+    throttling: bool,
+}
+
+impl RateLimiter {
+    pub fn new(id: usize, limits: Limits) -> Self {
+        Self {
+            id,
+            limits,
+
+            messages: TokenBucket::new(limits.messages_per_second, limits.messages_per_second),
+            bytes: TokenBucket::new(limits.bytes_per_second, limits.bytes_per_second),
+            strikes: TokenBucket::new(MAX_STRIKES, STRIKE_RECOVERY),
+            throttling: false,
+        }
+    }
+
+    /// Accounts for a message of `size` bytes before it is parsed, returning whether it should
+    /// be.
+    pub fn admit(&mut self, size: usize) -> Result<bool, Error> {
+        if size > self.limits.max_message_size {
+            stats::record(&STATS.oversized);
+            warn!("Connection {} sent a message of {size} bytes", self.id);
+
+            return Err(Error::MessageTooLarge(size));
+        }
+
+        let now = Instant::now();
+        if self.messages.take(1.0, now) && self.bytes.take(size as f64, now) {
+            self.throttling = false;
+
+            return Ok(true);
+        }
+
+        stats::record(&STATS.throttled);
+        if !self.throttling {
+            warn!("Connection {} is over its budget, throttling it", self.id);
+
+            self.throttling = true;
+        }
+
+        if !self.strikes.take(1.0, now) {
+            stats::record(&STATS.flooders);
+            warn!("Connection {} kept flooding ({STATS})", self.id);
+
+            return Err(Error::Flooding);
+        }
+
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_only_the_exact_password() {
+        assert!(password_matches("hunter2", "hunter2"));
+        assert!(password_matches("", ""));
+
+        for given in ["", "hunter", "hunter22", "Hunter2", "hunter3", "hunter2\0"] {
+            assert!(!password_matches(given, "hunter2"), "{given:?} matched");
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use protocol::message::{Encoding, ServerMessage};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::Error;
+
+pub use limit::{password_matches, Attempts, Limits};
+
+mod limit;
+pub mod tcp;
+pub mod udp;
+
+/// How many messages may queue up for a client before new ones are dropped.
+const OUTGOING_CAPACITY: usize = 256;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Creates a non-blocking socket bound to `address`.
+///
+/// IPv6 sockets take IPv4 clients too, so `[::]` listens on every address of both.
+fn bind(address: SocketAddr, kind: Type, protocol: Protocol) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(address), kind, Some(protocol))?;
+
+    if address.is_ipv6() {
+        socket.set_only_v6(false)?;
+    }
+
+    // Lets a restarted server listen again while the old connections are still winding down.
+    if cfg!(unix) && kind == Type::STREAM {
+        socket.set_reuse_address(true)?;
+    }
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+
+    Ok(socket)
+}
+
+/// The encoding a connection agreed on, shared between the game loop and the connection's tasks.
+#[derive(Debug, Default, Clone)]
+pub struct Negotiated(Arc<OnceLock<Encoding>>);
+
+impl Negotiated {
+    /// The encoding agreed on, or JSON while the handshake is still going.
+    pub fn get(&self) -> Encoding {
+        self.0.get().copied().unwrap_or_default()
+    }
+
+    pub fn set(&self, encoding: Encoding) {
+        let _ = self.0.set(encoding);
+    }
+
+    /// The encoding to send `message` in, which is always JSON when it answers the handshake.
+    pub fn for_message(&self, message: &ServerMessage) -> Encoding {
+        if message.is_handshake() {
+            return Encoding::Json;
+        }
+
+        self.get()
+    }
+}
+
+/// The game loop's handle to a client, whichever task is doing the actual IO.
+#[derive(Debug)]
+pub struct Connection {
+    id: usize,
+    /// Where the client is connecting from.
+    address: SocketAddr,
+    outgoing: mpsc::Sender<ServerMessage>,
+    encoding: Negotiated,
+}
+
+impl Connection {
+    pub fn new(address: SocketAddr) -> (Self, mpsc::Receiver<ServerMessage>) {
+        let (outgoing, receiver) = mpsc::channel(OUTGOING_CAPACITY);
+        let connection = Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            address,
+            outgoing,
+            encoding: Negotiated::default(),
+        };
+
+        (connection, receiver)
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The IP address the client is connecting from, where IPv4 clients of a dual-stack listener
+    /// show up as plain IPv4.
+    pub fn ip(&self) -> IpAddr {
+        self.address.ip().to_canonical()
+    }
+
+    pub fn encoding(&self) -> &Negotiated {
+        &self.encoding
+    }
+
+    /// Queues a message for the client without waiting on its socket.
+    ///
+    /// If the client is not keeping up, snapshots are dropped since a newer one is on its way,
+    /// but anything else means the client has stalled.
+    pub fn send(&self, message: ServerMessage) -> Result<(), Error> {
+        match self.outgoing.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(message)) if !message.is_reliable() => Ok(()),
+            Err(TrySendError::Full(_)) => Err(Error::Stalled),
+            // The connection's tasks have already reported why they stopped.
+            Err(TrySendError::Closed(_)) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, warn};
+use protocol::message::{self, ClientMessage, Encoding, Rejection, ServerMessage};
+use socket2::{Protocol, Type};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time;
+
+use crate::bans::{self, Bans};
+use crate::codec;
+use crate::connection::limit::RateLimiter;
+use crate::connection::{self, Connection, Limits, Negotiated};
+use crate::server::Inbound;
+use crate::shutdown::FlushGuard;
+use crate::Error;
+
+/// How many connections may wait to be accepted.
+const BACKLOG: i32 = 1024;
+
+/// How long a banned client gets to hear why it is turned away before it is hung up on.
+const REJECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Starts listening for connections on `address`.
+pub fn bind(address: SocketAddr) -> io::Result<TcpListener> {
+    let socket = connection::bind(address, Type::STREAM, Protocol::TCP)?;
+    socket.listen(BACKLOG)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Accepts connections until the game loop goes away, turning away those from banned addresses.
+///
+/// Every connection's writer holds on to `guard` until it has sent everything it was given.
+pub async fn listen(
+    listener: TcpListener,
+    inbound: mpsc::Sender<Inbound>,
+    limits: Limits,
+    guard: FlushGuard,
+    bans: Bans,
+) {
+    loop {
+        let result = tokio::select! {
+            result = listener.accept() => result,
+            _ = inbound.closed() => return,
+        };
+
+        let (socket, address) = match result {
+            Ok(accepted) => accepted,
+            Err(error) => {
+                warn!("Failed to accept a connection: {error}");
+
+                continue;
+            }
+        };
+
+        if let Some(rejection) = bans::rejection(&bans, address.ip()) {
+            debug!("Turned away a connection from {address}, which is banned");
+
+            tokio::spawn(turn_away(socket, rejection));
+            continue;
+        }
+
+        if let Err(error) = socket.set_nodelay(true) {
+            warn!("Failed to disable Nagle's algorithm: {error}");
+        }
+
+        spawn(socket, address, inbound.clone(), limits, guard.clone()).await;
+    }
+}
+
+/// Tells a banned client why it is not let in, without waiting for its handshake, and hangs up.
+async fn turn_away(mut socket: TcpStream, rejection: Rejection) {
+    let message = ServerMessage::Reject(rejection);
+
+    let _ = time::timeout(REJECT_TIMEOUT, async {
+        codec::write_message(&mut socket, &message, Encoding::Json).await?;
+        socket.shutdown().await?;
+
+        // Closing with the handshake still unread would reset the connection, which may throw
+        // the rejection away before the client gets to read it.
+        let mut buffer = [0; 1024];
+        while socket.read(&mut buffer).await? > 0 {}
+
+        Ok::<_, Error>(())
+    })
+    .await;
+}
+
+/// Hands a freshly accepted socket to the game loop and spawns its reader and writer tasks.
+async fn spawn(
+    socket: TcpStream,
+    address: SocketAddr,
+    inbound: mpsc::Sender<Inbound>,
+    limits: Limits,
+    guard: FlushGuard,
+) {
+    let (connection, outgoing) = Connection::new(address);
+    let id = connection.id();
+    let encoding = connection.encoding().clone();
+
+    if inbound.send(Inbound::Connected(connection)).await.is_err() {
+        return;
+    }
+
+    // The writer ends once the game loop lets go of the connection and everything it was given
+    // has been sent, and takes the reader down with it.
+    let (finished, stopped) = oneshot::channel();
+
+    let (reader, writer) = socket.into_split();
+    tokio::spawn(read(
+        id,
+        reader,
+        inbound.clone(),
+        limits,
+        encoding.clone(),
+        stopped,
+    ));
+    tokio::spawn(write(
+        id, writer, outgoing, inbound, encoding, guard, finished,
+    ));
+}
+
+async fn read(
+    id: usize,
+    mut reader: OwnedReadHalf,
+    inbound: mpsc::Sender<Inbound>,
+    limits: Limits,
+    encoding: Negotiated,
+    mut stopped: oneshot::Receiver<()>,
+) {
+    let mut limiter = RateLimiter::new(id, limits);
+
+    loop {
+        let result = tokio::select! {
+            result = receive(&mut reader, &mut limiter, &encoding) => result,
+            _ = &mut stopped => return,
+        };
+
+        let event = match result {
+            Ok(Some(message)) => Inbound::Message { id, message },
+            Ok(None) => continue,
+            Err(error) => Inbound::Disconnected { id, error },
+        };
+
+        let disconnected = matches!(event, Inbound::Disconnected { .. });
+        if inbound.send(event).await.is_err() || disconnected {
+            return;
+        }
+    }
+}
+
+/// Reads the next message, skipping the parsing if the connection is over its budget.
+async fn receive(
+    reader: &mut OwnedReadHalf,
+    limiter: &mut RateLimiter,
+    encoding: &Negotiated,
+) -> Result<Option<ClientMessage>, Error> {
+    let frame = codec::read_frame(reader).await?;
+    if !limiter.admit(frame.len())? {
+        return Ok(None);
+    }
+
+    Ok(Some(message::decode(&frame, encoding.get())?))
+}
+
+async fn write(
+    id: usize,
+    mut writer: OwnedWriteHalf,
+    mut outgoing: mpsc::Receiver<ServerMessage>,
+    inbound: mpsc::Sender<Inbound>,
+    encoding: Negotiated,
+    _guard: FlushGuard,
+    _finished: oneshot::Sender<()>,
+) {
+    while let Some(message) = outgoing.recv().await {
+        let encoding = encoding.for_message(&message);
+
+        if let Err(error) = codec::write_message(&mut writer, &message, encoding).await {
+            let _ = inbound.send(Inbound::Disconnected { id, error }).await;
+
+            return;
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use protocol::channel::{Endpoint, RESEND_TIMEOUT};
+use protocol::message::{self, ClientMessage, Encoding, Rejection, ServerMessage};
+use protocol::packet::Packet;
+use socket2::{Protocol, Type};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio::time;
+
+use crate::bans::{self, Bans};
+use crate::connection::limit::RateLimiter;
+use crate::connection::{self, Connection, Limits, Negotiated};
+use crate::server::Inbound;
+use crate::shutdown::FlushGuard;
+use crate::Error;
+
+/// How long a peer may stay silent before we consider it gone.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many messages may queue up for the socket across all peers.
+const OUTGOING_CAPACITY: usize = 1024;
+
+#[derive(Debug)]
+struct Peer {
+    id: usize,
+    endpoint: Endpoint,
+    encoding: Negotiated,
+    limiter: RateLimiter,
+    last_seen: Instant,
+}
+
+/// Opens a socket for peers to reach us on at `address`.
+pub fn bind(address: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = connection::bind(address, Type::DGRAM, Protocol::UDP)?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+/// Serves peers on a single socket until the game loop goes away and the last of them has been
+/// sent what it was given, holding on to `guard` until then.
+///
+/// Reliable messages are resent until acknowledged, while snapshots are sent once and only the
+/// newest one is ever delivered, so a lost packet never holds up the ones behind it. Banned
+/// addresses are told so, and nothing more.
+pub async fn listen(
+    socket: UdpSocket,
+    inbound: mpsc::Sender<Inbound>,
+    limits: Limits,
+    _guard: FlushGuard,
+    bans: Bans,
+) {
+    let mut peers: HashMap<SocketAddr, Peer> = HashMap::new();
+
+    // Every peer's messages are funneled into one queue, as there is only one socket to write to.
+    // They carry the id of their connection, as a new peer may have taken over the address since.
+    // A `None` means the game loop has dropped the connection.
+    let (outgoing, mut receiver) = mpsc::channel(OUTGOING_CAPACITY);
+
+    let mut buffer = vec![0; u16::MAX as usize];
+    let mut resend = time::interval(RESEND_TIMEOUT / 2);
+
+    loop {
+        tokio::select! {
+            result = socket.recv_from(&mut buffer) => {
+                let (size, address) = match result {
+                    Ok(received) => received,
+                    Err(error) => {
+                        warn!("Failed to receive a datagram: {error}");
+
+                        continue;
+                    }
+                };
+
+                let peer = match peers.entry(address) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    // Strangers get nothing back, not even an ack, until they say hello.
+                    Entry::Vacant(_) if !is_handshake(&buffer[..size]) => continue,
+                    Entry::Vacant(entry) => {
+                        if let Some(rejection) = bans::rejection(&bans, address.ip()) {
+                            debug!("Turned away a handshake from {address}, which is banned");
+
+                            turn_away(&socket, address, rejection).await;
+                            continue;
+                        }
+
+                        let Some(peer) = connect(address, outgoing.clone(), &inbound, limits).await else {
+                            return;
+                        };
+
+                        entry.insert(peer)
+                    }
+                };
+                peer.last_seen = Instant::now();
+
+                let id = peer.id;
+                let events = match peer.limiter.admit(size) {
+                    Ok(true) => receive(peer, &socket, address, &buffer[..size]).await,
+                    Ok(false) => continue,
+                    Err(error) => vec![Inbound::Disconnected { id, error }],
+                };
+
+                for event in events {
+                    if matches!(event, Inbound::Disconnected { .. }) {
+                        peers.remove(&address);
+                    }
+
+                    if inbound.send(event).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            Some((id, address, message)) = receiver.recv() => {
+                let Some(peer) = peers.get_mut(&address).filter(|peer| peer.id == id) else {
+                    continue;
+                };
+
+                let Some(message) = message else {
+                    peers.remove(&address);
+
+                    continue;
+                };
+
+                let encoding = peer.encoding.for_message(&message);
+                match wrap(&mut peer.endpoint, &message, encoding) {
+                    Ok(datagram) => send(&socket, address, &datagram).await,
+                    // A peer that stopped acknowledging is not worth resending to any longer.
+                    Err(Error::Protocol(protocol::Error::SendWindowFull)) => {
+                        peers.remove(&address);
+
+                        let event = Inbound::Disconnected { id, error: Error::Stalled };
+                        if inbound.send(event).await.is_err() {
+                            return;
+                        }
+                    }
+                    Err(error) => warn!("Failed to send {message:?} to {address}: {error}"),
+                }
+            }
+            _ = resend.tick() => {
+                let now = Instant::now();
+
+                let mut timed_out = Vec::new();
+                for (address, peer) in peers.iter_mut() {
+                    if now.duration_since(peer.last_seen) > TIMEOUT {
+                        timed_out.push(*address);
+
+                        continue;
+                    }
+
+                    for datagram in peer.endpoint.resend(now) {
+                        send(&socket, *address, &datagram).await;
+                    }
+                }
+
+                for address in timed_out {
+                    let peer = peers.remove(&address).expect("peer timed out");
+                    let event = Inbound::Disconnected { id: peer.id, error: Error::TimedOut };
+
+                    if inbound.send(event).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            _ = inbound.closed(), if peers.is_empty() => return,
+        }
+    }
+}
+
+/// Passes a datagram to the peer's endpoint, acknowledging it and turning what it delivers into
+/// events for the game loop.
+async fn receive(
+    peer: &mut Peer,
+    socket: &UdpSocket,
+    address: SocketAddr,
+    datagram: &[u8],
+) -> Vec<Inbound> {
+    let id = peer.id;
+
+    match peer.endpoint.receive(datagram) {
+        Ok(received) => {
+            if let Some(reply) = received.reply {
+                send(socket, address, &reply).await;
+            }
+
+            received
+                .payloads
+                .iter()
+                .map(
+                    |payload| match message::decode(payload, peer.encoding.get()) {
+                        Ok(message) => Inbound::Message { id, message },
+                        Err(error) => Inbound::Disconnected {
+                            id,
+                            error: error.into(),
+                        },
+                    },
+                )
+                .collect()
+        }
+        Err(error) => vec![Inbound::Disconnected {
+            id,
+            error: error.into(),
+        }],
+    }
+}
+
+/// Whether a datagram from an unknown address opens a connection, which only the first reliable
+/// packet of a handshake does.
+fn is_handshake(datagram: &[u8]) -> bool {
+    let Ok(Packet::Reliable {
+        sequence: 0,
+        payload,
+    }) = Packet::decode(datagram)
+    else {
+        return false;
+    };
+
+    message::decode::<ClientMessage>(&payload, Encoding::Json)
+        .is_ok_and(|message| message.is_handshake())
+}
+
+/// Tells a banned client why it is not let in. No peer is kept for it, so the rejection is never
+/// resent, but a lost one is answered again once the client resends its handshake.
+async fn turn_away(socket: &UdpSocket, address: SocketAddr, rejection: Rejection) {
+    let message = ServerMessage::Reject(rejection);
+
+    match wrap(&mut Endpoint::new(), &message, Encoding::Json) {
+        Ok(datagram) => send(socket, address, &datagram).await,
+        Err(error) => warn!("Failed to send {message:?} to {address}: {error}"),
+    }
+}
+
+/// Hands a new peer to the game loop, forwarding whatever it sends them into the shared queue.
+async fn connect(
+    address: SocketAddr,
+    outgoing: mpsc::Sender<(usize, SocketAddr, Option<ServerMessage>)>,
+    inbound: &mpsc::Sender<Inbound>,
+    limits: Limits,
+) -> Option<Peer> {
+    let (connection, mut messages) = Connection::new(address);
+    let id = connection.id();
+    let encoding = connection.encoding().clone();
+
+    inbound.send(Inbound::Connected(connection)).await.ok()?;
+
+    tokio::spawn(async move {
+        while let Some(message) = messages.recv().await {
+            if outgoing.send((id, address, Some(message))).await.is_err() {
+                return;
+            }
+        }
+
+        let _ = outgoing.send((id, address, None)).await;
+    });
+
+    Some(Peer {
+        id,
+        endpoint: Endpoint::new(),
+        encoding,
+        limiter: RateLimiter::new(id, limits),
+        last_seen: Instant::now(),
+    })
+}
+
+fn wrap(
+    endpoint: &mut Endpoint,
+    message: &ServerMessage,
+    encoding: Encoding,
+) -> Result<Vec<u8>, Error> {
+    let payload = message::encode(message, encoding)?;
+
+    let datagram = if message.is_reliable() {
+        endpoint.send_reliable(payload, Instant::now())?
+    } else {
+        endpoint.send_unreliable(payload)?
+    };
+
+    Ok(datagram)
+}
+
+async fn send(socket: &UdpSocket, address: SocketAddr, datagram: &[u8]) {
+    if let Err(error) = socket.send_to(datagram, address).await {
+        warn!("Failed to send a datagram to {address}: {error}");
+    }
+}
@@ -0,0 +1,590 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use env_logger::Target;
+use log::warn;
+use protocol::rcon::{parse_duration, BanTarget, Command, PlayerStatus, RconResponse};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, ExternalPrinter};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::server::Inbound;
+
+/// Everything that can be typed into the console, with its arguments and what it does.
+const COMMANDS: [(&str, &str); 12] = [
+    ("status", "Describes the server and everyone on it"),
+    ("kick <player>", "Removes a player from the match"),
+    (
+        "ban <who> [time] [reason]",
+        "Keeps a player, address or name out, for a time such as 7d or for good",
+    ),
+    ("say <text>", "Shows a message to every player"),
+    (
+        "map <name>",
+        "Switches to another map, restarting the round",
+    ),
+    ("restart", "Starts the round over"),
+    ("pause", "Freezes the match"),
+    ("unpause", "Lets a paused match carry on"),
+    ("get <variable>", "Reads a server variable"),
+    ("set <variable> <value>", "Changes a server variable"),
+    ("help", "Lists these commands"),
+    ("quit", "Shuts the server down"),
+];
+
+/// Prints log lines above the line being typed, for as long as the console has the terminal.
+type Printer = Arc<Mutex<Option<Box<dyn ExternalPrinter + Send>>>>;
+
+/// Commands typed into the terminal the server runs in, read on a thread of their own so the
+/// game loop never waits on the admin.
+pub struct Console {
+    editor: Editor<Helper, DefaultHistory>,
+    printer: Printer,
+}
+
+impl Console {
+    /// Takes over the terminal, unless the server is not running in one.
+    pub fn open() -> Option<Self> {
+        if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+            return None;
+        }
+
+        match Editor::new() {
+            Ok(editor) => Some(Self {
+                editor,
+                printer: Printer::default(),
+            }),
+            Err(error) => {
+                eprintln!("Failed to open the console: {error}");
+
+                None
+            }
+        }
+    }
+
+    /// Where to send log lines so they show up above the line being typed, rather than
+    /// through it.
+    pub fn log_target(&mut self) -> Option<Target> {
+        let printer = self.editor.create_external_printer().ok()?;
+        *self.printer.lock().expect("the printer is never poisoned") = Some(Box::new(printer));
+
+        Some(Target::Pipe(Box::new(LogWriter {
+            printer: self.printer.clone(),
+            line: Vec::new(),
+        })))
+    }
+
+    /// Starts reading commands, which are carried out by the game loop behind `inbound`.
+    ///
+    /// The terminal is the console's until the returned guard releases it, which has to happen
+    /// before the server shuts down.
+    pub fn spawn(mut self, inbound: mpsc::Sender<Inbound>) -> io::Result<TerminalGuard> {
+        self.editor.set_helper(Some(Helper { inbound }));
+
+        let guard = TerminalGuard::save(self.printer.clone());
+        let terminal = guard.clone();
+        thread::Builder::new()
+            .name("console".to_string())
+            .spawn(move || self.run(terminal))?;
+
+        Ok(guard)
+    }
+
+    fn run(mut self, terminal: TerminalGuard) {
+        loop {
+            let line = match self.editor.readline("> ") {
+                Ok(line) => line,
+                // Ctrl-C and Ctrl-D stop the server, as they would without a console.
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => "quit".to_string(),
+                Err(error) => {
+                    warn!("The console stopped reading commands: {error}");
+
+                    return;
+                }
+            };
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let _ = self.editor.add_history_entry(line);
+
+            let helper = self.editor.helper().expect("the helper is set on spawn");
+            let command = match helper.parse(line) {
+                Ok(Input::Help) => {
+                    for (usage, about) in COMMANDS {
+                        println!("  {usage:<28}{about}");
+                    }
+
+                    continue;
+                }
+                Ok(Input::Quit) => {
+                    terminal.release();
+
+                    let reason = "stopped from the console".to_string();
+                    let _ = helper.inbound.blocking_send(Inbound::Shutdown { reason });
+
+                    return;
+                }
+                Ok(Input::Command(command)) => command,
+                Err(error) => {
+                    println!("{error}");
+
+                    continue;
+                }
+            };
+
+            match helper.request(command) {
+                Some(RconResponse::Status(status)) => print!("{status}"),
+                Some(RconResponse::Value { name, value }) => println!("{name} = {value:?}"),
+                Some(RconResponse::Error(error)) => println!("{error}"),
+                Some(RconResponse::LoggedIn | RconResponse::Done) => {}
+                // The game loop has shut down.
+                None => return,
+            }
+        }
+    }
+}
+
+/// Gives the terminal back the way it was before the console took it over, with log lines going
+/// straight to it from then on.
+///
+/// The console thread may be stuck waiting for a line when the server stops, with the terminal
+/// still in raw mode and the last log lines waiting for it to print them, and nothing else would
+/// put either right. Every clone releases the same terminal, which only happens once.
+#[derive(Clone)]
+pub struct TerminalGuard(Arc<Mutex<Option<Saved>>>);
+
+/// What releasing the terminal puts back.
+struct Saved {
+    #[cfg(unix)]
+    settings: Option<nix::sys::termios::Termios>,
+    printer: Printer,
+}
+
+impl TerminalGuard {
+    fn save(printer: Printer) -> Self {
+        Self(Arc::new(Mutex::new(Some(Saved {
+            #[cfg(unix)]
+            settings: nix::sys::termios::tcgetattr(io::stdin()).ok(),
+            printer,
+        }))))
+    }
+
+    pub fn release(&self) {
+        self.0
+            .lock()
+            .expect("the terminal is never poisoned")
+            .take();
+    }
+}
+
+impl Drop for Saved {
+    fn drop(&mut self) {
+        self.printer
+            .lock()
+            .expect("the printer is never poisoned")
+            .take();
+
+        #[cfg(unix)]
+        if let Some(settings) = &self.settings {
+            use nix::sys::termios::{tcsetattr, SetArg};
+
+            let _ = tcsetattr(io::stdin(), SetArg::TCSANOW, settings);
+        }
+
+        // Leave what comes next a line of its own, rather than after our prompt.
+        println!();
+    }
+}
+
+/// What a line typed into the console asks for.
+enum Input {
+    Help,
+    Quit,
+    Command(Command),
+}
+
+/// Parses commands and completes them, looking players up through the game loop.
+struct Helper {
+    inbound: mpsc::Sender<Inbound>,
+}
+
+impl Helper {
+    fn parse(&self, line: &str) -> Result<Input, String> {
+        let (name, argument) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, argument)| (name, argument.trim()));
+
+        let command = match (name, argument) {
+            ("help", "") => return Ok(Input::Help),
+            ("quit", "") => return Ok(Input::Quit),
+            ("status", "") => Command::Status,
+            ("kick", player) if !player.is_empty() => Command::Kick {
+                id: self.find(player)?,
+            },
+            ("ban", argument) if !argument.is_empty() => self.ban(argument)?,
+            ("say", text) if !text.is_empty() => Command::Say {
+                text: text.to_string(),
+            },
+            ("map", map) if !map.is_empty() => Command::ChangeLevel {
+                map: map.to_string(),
+            },
+            ("restart", "") => Command::RestartRound,
+            ("pause", "") => Command::Pause { paused: true },
+            ("unpause", "") => Command::Pause { paused: false },
+            ("get", name) if !name.is_empty() => Command::Get {
+                name: name.to_string(),
+            },
+            ("set", argument) => {
+                let Some((name, value)) = argument.split_once(char::is_whitespace) else {
+                    return Err(usage("set"));
+                };
+
+                Command::Set {
+                    name: name.to_string(),
+                    value: value.trim().to_string(),
+                }
+            }
+            _ if COMMANDS.iter().any(|(usage, _)| command_of(usage) == name) => {
+                return Err(usage(name))
+            }
+            _ => return Err(format!("Unknown command {name:?}, try help")),
+        };
+
+        Ok(Input::Command(command))
+    }
+
+    /// Parses the arguments of a ban, where the target is a player on the server, or else an
+    /// address or a name.
+    fn ban(&self, argument: &str) -> Result<Command, String> {
+        let (target, rest) = argument
+            .split_once(char::is_whitespace)
+            .map_or((argument, ""), |(target, rest)| (target, rest.trim()));
+
+        let online = self
+            .players()
+            .iter()
+            .any(|p| p.name == target || p.id.to_string() == target);
+        let target = if online {
+            BanTarget::Player(self.find(target)?)
+        } else if let Ok(ip) = target.parse() {
+            BanTarget::Address(ip)
+        } else {
+            BanTarget::Name(target.to_string())
+        };
+
+        // The time is optional, so anything that is not one starts the reason.
+        let (time, reason) = rest
+            .split_once(char::is_whitespace)
+            .map_or((rest, ""), |(time, reason)| (time, reason.trim()));
+        let (duration, reason) = match parse_duration(time) {
+            Ok(duration) => (Some(duration), reason),
+            Err(_) => (None, rest),
+        };
+
+        Ok(Command::Ban {
+            target,
+            duration,
+            reason: Some(reason.to_string()).filter(|reason| !reason.is_empty()),
+        })
+    }
+
+    /// Looks up the id of a player given by name or id.
+    fn find(&self, player: &str) -> Result<usize, String> {
+        let players = self.players();
+
+        let named: Vec<_> = players.iter().filter(|p| p.name == player).collect();
+        match named[..] {
+            [found] => return Ok(found.id),
+            [] => {}
+            _ => {
+                return Err(format!(
+                    "Several players go by {player}, use their id instead"
+                ))
+            }
+        }
+
+        players
+            .iter()
+            .find(|p| p.id.to_string() == player)
+            .map(|p| p.id)
+            .ok_or_else(|| format!("There is no player {player:?}"))
+    }
+
+    fn players(&self) -> Vec<PlayerStatus> {
+        match self.request(Command::Status) {
+            Some(RconResponse::Status(status)) => status.players,
+            _ => Vec::new(),
+        }
+    }
+
+    /// Has the game loop carry out `command`, waiting for its answer. Gives up if the game loop
+    /// has gone away.
+    fn request(&self, command: Command) -> Option<RconResponse> {
+        let (reply, response) = oneshot::channel();
+        self.inbound
+            .blocking_send(Inbound::Command { command, reply })
+            .ok()?;
+
+        response.blocking_recv().ok()
+    }
+}
+
+impl Completer for Helper {
+    type Candidate = String;
+
+    /// Completes the names of commands, and of the players to kick or ban.
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+
+        let Some((name, argument)) = line.split_once(char::is_whitespace) else {
+            let commands = COMMANDS
+                .iter()
+                .map(|(usage, _)| command_of(usage))
+                .filter(|command| command.starts_with(line))
+                .map(|command| format!("{command} "))
+                .collect();
+
+            return Ok((0, commands));
+        };
+
+        if !matches!(name, "kick" | "ban") {
+            return Ok((pos, Vec::new()));
+        }
+
+        // Only the first argument of a ban is a name.
+        let argument = argument.trim_start();
+        if name == "ban" && argument.contains(char::is_whitespace) {
+            return Ok((pos, Vec::new()));
+        }
+
+        let names = self
+            .players()
+            .into_iter()
+            .map(|player| player.name)
+            .filter(|player| player.starts_with(argument))
+            .collect();
+
+        Ok((pos - argument.len(), names))
+    }
+}
+
+impl Hinter for Helper {
+    type Hint = String;
+}
+
+impl Highlighter for Helper {}
+
+impl Validator for Helper {}
+
+impl rustyline::Helper for Helper {}
+
+/// Collects a log line as it is written, and hands it to the console once it is complete, or
+/// writes it to stderr once the console has let go of the terminal.
+struct LogWriter {
+    printer: Printer,
+    line: Vec<u8>,
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(data);
+
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.line.is_empty() {
+            return Ok(());
+        }
+
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+
+        match self
+            .printer
+            .lock()
+            .expect("the printer is never poisoned")
+            .as_mut()
+        {
+            Some(printer) => printer.print(line).map_err(io::Error::other),
+            None => io::stderr().write_all(line.as_bytes()),
+        }
+    }
+}
+
+/// The name of the command in a line of `COMMANDS`.
+fn command_of(usage: &str) -> &str {
+    usage.split(' ').next().unwrap_or(usage)
+}
+
+fn usage(name: &str) -> String {
+    let usage = COMMANDS
+        .iter()
+        .map(|(usage, _)| *usage)
+        .find(|usage| command_of(usage) == name)
+        .unwrap_or(name);
+
+    format!("Usage: {usage}")
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::rcon::Status;
+
+    use super::*;
+
+    /// A helper whose game loop has the given players on it.
+    fn helper(players: &[(usize, &str)]) -> Helper {
+        let players: Vec<_> = players
+            .iter()
+            .map(|&(id, name)| PlayerStatus {
+                id,
+                name: name.to_string(),
+                address: None,
+                health: 100.0,
+                kills: 0,
+                deaths: 0,
+            })
+            .collect();
+
+        let (inbound, mut receiver) = mpsc::channel(1);
+        thread::spawn(move || {
+            while let Some(message) = receiver.blocking_recv() {
+                if let Inbound::Command { reply, .. } = message {
+                    let _ = reply.send(RconResponse::Status(Status {
+                        hostname: String::new(),
+                        map: String::new(),
+                        phase: String::new(),
+                        paused: false,
+                        tick: 0,
+                        players: players.clone(),
+                    }));
+                }
+            }
+        });
+
+        Helper { inbound }
+    }
+
+    fn command(helper: &Helper, line: &str) -> Command {
+        match helper.parse(line) {
+            Ok(Input::Command(command)) => command,
+            Ok(_) => panic!("{line:?} is not a command"),
+            Err(error) => panic!("{line:?} did not parse: {error}"),
+        }
+    }
+
+    #[test]
+    fn parses_commands_and_their_arguments() {
+        let helper = helper(&[(3, "alice")]);
+
+        assert!(matches!(helper.parse("help"), Ok(Input::Help)));
+        assert!(matches!(helper.parse("quit"), Ok(Input::Quit)));
+        assert!(matches!(command(&helper, "status"), Command::Status));
+        assert!(matches!(
+            command(&helper, "kick alice"),
+            Command::Kick { id: 3 }
+        ));
+        assert!(matches!(
+            command(&helper, "say hello  there"),
+            Command::Say { text } if text == "hello  there"
+        ));
+        assert!(matches!(
+            command(&helper, "set hostname  My server"),
+            Command::Set { name, value } if name == "hostname" && value == "My server"
+        ));
+        assert!(matches!(
+            command(&helper, "pause"),
+            Command::Pause { paused: true }
+        ));
+        assert!(matches!(
+            command(&helper, "unpause"),
+            Command::Pause { paused: false }
+        ));
+    }
+
+    #[test]
+    fn explains_how_to_use_a_command_missing_its_arguments() {
+        let helper = helper(&[]);
+
+        assert_eq!(helper.parse("kick").err().unwrap(), "Usage: kick <player>");
+        assert!(helper
+            .parse("set hostname")
+            .err()
+            .unwrap()
+            .starts_with("Usage: set "));
+        assert!(helper
+            .parse("status now")
+            .err()
+            .unwrap()
+            .starts_with("Usage: status"));
+        assert!(helper
+            .parse("teleport alice")
+            .err()
+            .unwrap()
+            .starts_with("Unknown command \"teleport\""));
+    }
+
+    #[test]
+    fn finds_players_by_name_before_id() {
+        let helper = helper(&[(1, "alice"), (2, "1"), (3, "bob"), (4, "bob")]);
+
+        assert_eq!(helper.find("alice"), Ok(1));
+        assert_eq!(helper.find("3"), Ok(3));
+        assert_eq!(helper.find("1"), Ok(2));
+        assert!(helper.find("bob").unwrap_err().contains("Several players"));
+        assert!(helper.find("mallory").is_err());
+    }
+
+    #[test]
+    fn bans_players_addresses_and_names() {
+        let helper = helper(&[(5, "alice")]);
+
+        assert!(matches!(
+            helper.ban("alice 7d griefing").unwrap(),
+            Command::Ban {
+                target: BanTarget::Player(5),
+                duration: Some(604_800),
+                reason: Some(reason),
+            } if reason == "griefing"
+        ));
+        assert!(matches!(
+            helper.ban("10.0.0.1 being rude").unwrap(),
+            Command::Ban {
+                target: BanTarget::Address(ip),
+                duration: None,
+                reason: Some(reason),
+            } if ip.to_string() == "10.0.0.1" && reason == "being rude"
+        ));
+        assert!(matches!(
+            helper.ban("10.0.0.1 30m").unwrap(),
+            Command::Ban {
+                target: BanTarget::Address(_),
+                duration: Some(1800),
+                reason: None,
+            }
+        ));
+        assert!(matches!(
+            helper.ban("mallory").unwrap(),
+            Command::Ban {
+                target: BanTarget::Name(name),
+                duration: None,
+                reason: None,
+            } if name == "mallory"
+        ));
+    }
+}
@@ -1,9 +1,70 @@
+use protocol::message::DisconnectReason;
 use thiserror::Error;
 
+/// Something that ends a connection.
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("IO error: {0}")]
     Io(#[from] tokio::io::Error),
-    #[error("Serde error: {0}")]
-    Serde(#[from] serde_json::Error),
+    #[error("Protocol error: {0}")]
+    Protocol(#[from] protocol::Error),
+    #[error("Unexpected message: {0:?}")]
+    UnexpectedMessage(protocol::message::ClientMessage),
+    #[error("Player disconnected")]
+    Disconnected,
+    #[error("Connection timed out")]
+    TimedOut,
+    #[error("Player is not keeping up with their messages")]
+    Stalled,
+    #[error("Message of {0} bytes is larger than allowed")]
+    MessageTooLarge(usize),
+    #[error("Player kept sending more than their budget allows")]
+    Flooding,
+    #[error("Illegal movement: {0}")]
+    IllegalMovement(#[from] crate::player::Violation),
+    #[error("Kicked by an admin")]
+    Kicked,
+    #[error("Banned by an admin")]
+    Banned,
+}
+
+impl Error {
+    /// The reason given to players when this error ends a connection.
+    pub fn reason(&self) -> DisconnectReason {
+        match self {
+            Self::Io(_) => DisconnectReason::ConnectionLost,
+            Self::Protocol(_) => DisconnectReason::InvalidMessage,
+            Self::UnexpectedMessage(_) => DisconnectReason::UnexpectedMessage,
+            Self::Disconnected => DisconnectReason::Quit,
+            Self::TimedOut => DisconnectReason::TimedOut,
+            Self::Stalled => DisconnectReason::Stalled,
+            Self::MessageTooLarge(_) => DisconnectReason::MessageTooLarge,
+            Self::Flooding => DisconnectReason::Flooding,
+            Self::IllegalMovement(_) => DisconnectReason::IllegalMovement,
+            Self::Kicked => DisconnectReason::Kicked,
+            Self::Banned => DisconnectReason::Banned,
+        }
+    }
+}
+
+/// Something that keeps the server from starting.
+#[derive(Debug, Error)]
+pub enum StartupError {
+    #[error("IO error: {0}")]
+    Io(#[from] tokio::io::Error),
+    #[error("Invalid config: {0}")]
+    Config(#[from] toml::de::Error),
+    #[error("Invalid ban list: {0}")]
+    Bans(toml::de::Error),
+    #[error("Invalid config:\n  {}", .0.join("\n  "))]
+    Invalid(Vec<String>),
+}
+
+/// Something that keeps the ban list from being written to its file.
+#[derive(Debug, Error)]
+pub enum SaveError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to serialize the ban list: {0}")]
+    Serialize(#[from] toml::ser::Error),
 }
@@ -1,45 +1,425 @@
+use std::env;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
 use clap::Parser;
+use env_logger::Env;
+use log::{error, info, warn};
+use protocol::message::{Encoding, GameMode};
 
-use error::Error;
+use error::{Error, StartupError};
 
-use crate::player::Player;
-use crate::server::Server;
+use crate::bans::{BanList, Bans};
+use crate::config::{Config, Rcon, Round, Transport};
+use crate::connection::Limits;
+use crate::console::Console;
+use crate::player::{Authority, Player, Response};
+use crate::server::{Inbound, Server, Settings};
+use crate::shutdown::FlushGuard;
 
-use tokio::net::TcpListener;
+use tokio::sync::mpsc;
 
+mod bans;
+mod codec;
+mod config;
+mod connection;
+mod console;
 mod error;
 mod player;
+mod rcon;
 mod server;
+mod shutdown;
+mod stats;
+
+/// How long connections get to send their last messages once the server shuts down.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// A simple game server.
+///
+/// Settings are read from the config file if one is given, and any flag overrides its key.
+///
+/// Passwords have no flags, since anyone on the machine can list the command line. They are
+/// read from the config file, or from SERVER_PASSWORD and RCON_PASSWORD, which override it.
 #[derive(Debug, Parser)]
 #[command(version, about)]
 struct Args {
-    /// The number of players to wait for.
-    #[arg(short, long, default_value = "1")]
-    count: usize,
+    /// The TOML file to read the server's settings from.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// The name of the server, shown to players when they join.
+    #[arg(long)]
+    hostname: Option<String>,
+
+    /// The addresses to listen on, such as 0.0.0.0:7512, or [::]:7512 for IPv4 and IPv6 alike.
+    #[arg(short, long, num_args = 1..)]
+    bind: Option<Vec<SocketAddr>>,
+
+    /// The transport to accept clients over.
+    #[arg(long, value_enum)]
+    transport: Option<Transport>,
+
+    /// The number of simulation ticks per second, usually 64 or 128.
+    #[arg(short, long)]
+    tick_rate: Option<u32>,
+
+    /// The number of ready players needed to start the match.
+    #[arg(long)]
+    min_players: Option<usize>,
+
+    /// The number of players a match has room for.
+    #[arg(long)]
+    max_players: Option<usize>,
+
+    /// The file banned players are kept in.
+    #[arg(long)]
+    bans: Option<PathBuf>,
+
+    /// The map the server starts on.
+    #[arg(long)]
+    map: Option<String>,
+
+    /// The rules the match is played by.
+    #[arg(long)]
+    mode: Option<GameMode>,
+
+    /// How long a round lasts, in seconds, or 0 for no limit.
+    #[arg(long)]
+    time_limit: Option<u64>,
+
+    /// The addresses the remote console listens on.
+    #[arg(long, num_args = 1..)]
+    rcon_bind: Option<Vec<SocketAddr>>,
+
+    /// How long the countdown before the match starts is, in seconds.
+    #[arg(long)]
+    countdown: Option<u64>,
+
+    /// How long a player who lost their connection keeps their place, in seconds.
+    #[arg(long)]
+    grace_period: Option<u64>,
+
+    /// Who decides where players move.
+    #[arg(long, value_enum)]
+    movement_authority: Option<Authority>,
+
+    /// What happens to players who report moves they could not have made.
+    #[arg(long, value_enum)]
+    movement_violation: Option<Response>,
+
+    /// How many messages a connection may send per second before it is throttled, which has to
+    /// leave room for two every tick.
+    #[arg(long)]
+    max_messages_per_second: Option<u32>,
+
+    /// How many bytes a connection may send per second before it is throttled.
+    #[arg(long)]
+    max_bytes_per_second: Option<u32>,
+
+    /// The size of the largest message a connection may send, in bytes.
+    #[arg(long)]
+    max_message_size: Option<usize>,
+
+    /// The encoding to speak with clients that support it, "bincode" or "json".
+    ///
+    /// JSON is always available as a fallback, and readable for debugging.
+    #[arg(long)]
+    encoding: Option<Encoding>,
+}
+
+impl Args {
+    /// Overrides the keys of `config` that were given as flags, and the passwords that were set
+    /// in the environment.
+    fn apply(&self, config: Config) -> Config {
+        Config {
+            hostname: self.hostname.clone().unwrap_or(config.hostname),
+            bind: self.bind.clone().unwrap_or(config.bind),
+            transport: self.transport.unwrap_or(config.transport),
+            tick_rate: self.tick_rate.unwrap_or(config.tick_rate),
+
+            min_players: self.min_players.unwrap_or(config.min_players),
+            max_players: self.max_players.unwrap_or(config.max_players),
+            password: env::var("SERVER_PASSWORD").ok().or(config.password),
+            bans: self.bans.clone().unwrap_or(config.bans),
+
+            map: self.map.clone().unwrap_or(config.map),
+            mode: self.mode.unwrap_or(config.mode),
+            round: Round {
+                time_limit: self.time_limit.unwrap_or(config.round.time_limit),
+            },
+            rcon: Rcon {
+                bind: self.rcon_bind.clone().unwrap_or(config.rcon.bind),
+                password: env::var("RCON_PASSWORD").ok().or(config.rcon.password),
+            },
+
+            countdown: self.countdown.unwrap_or(config.countdown),
+            grace_period: self.grace_period.unwrap_or(config.grace_period),
+
+            movement_authority: self.movement_authority.unwrap_or(config.movement_authority),
+            movement_violation: self.movement_violation.unwrap_or(config.movement_violation),
+
+            max_messages_per_second: self
+                .max_messages_per_second
+                .unwrap_or(config.max_messages_per_second),
+            max_bytes_per_second: self
+                .max_bytes_per_second
+                .unwrap_or(config.max_bytes_per_second),
+            max_message_size: self.max_message_size.unwrap_or(config.max_message_size),
+
+            encoding: self.encoding.unwrap_or(config.encoding),
+        }
+    }
+
+    /// Loads the config file, if there is one, and applies the flags on top.
+    fn load(&self) -> Result<Config, StartupError> {
+        let config = match &self.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        let config = self.apply(config);
+        config.validate().map_err(StartupError::Invalid)?;
+
+        Ok(config)
+    }
+}
+
+/// Reloads the config whenever the server receives SIGHUP, so the password and the ban list can
+/// be changed without restarting. Everything else needs a restart to take effect.
+///
+/// `password` is the one the server started with. The password is only sent on when it differs
+/// from the last one loaded, so an admin's change to it holds until the file changes too.
+#[cfg(unix)]
+async fn reload(
+    args: Args,
+    mut password: Option<String>,
+    inbound: mpsc::Sender<Inbound>,
+    bans: Bans,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(error) => {
+            warn!("Failed to listen for reload signals: {error}");
 
-    /// The port to listen on.
-    #[arg(short, long, default_value = "7512")]
-    port: u16,
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        let config = match args.load() {
+            Ok(config) => config,
+            Err(error) => {
+                match &args.config {
+                    Some(path) => warn!("Not reloading {}: {error}", path.display()),
+                    None => warn!("Not reloading: {error}"),
+                }
+
+                continue;
+            }
+        };
+
+        if config.password != password {
+            info!("The configured password changed, replacing any an admin has set since");
+
+            password = config.password;
+            if inbound
+                .send(Inbound::SetPassword(password.clone()))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+
+        match BanList::load(&config.bans) {
+            Ok(reloaded) => {
+                *bans.write().expect("the ban list is never poisoned") = reloaded;
+
+                info!("The ban list has been reloaded");
+            }
+            Err(error) => warn!(
+                "Not reloading the ban list from {}: {error}",
+                config.bans.display()
+            ),
+        }
+    }
+}
+
+/// Starts accepting clients on `address`.
+fn listen(
+    address: SocketAddr,
+    transport: Transport,
+    inbound: &mpsc::Sender<Inbound>,
+    limits: Limits,
+    guard: &FlushGuard,
+    bans: &Bans,
+) -> io::Result<()> {
+    match transport {
+        Transport::Tcp => {
+            let listener = connection::tcp::bind(address)?;
+            info!("Listening on tcp://{}", listener.local_addr()?);
+
+            let listen = connection::tcp::listen(
+                listener,
+                inbound.clone(),
+                limits,
+                guard.clone(),
+                bans.clone(),
+            );
+            tokio::spawn(listen);
+        }
+        Transport::Udp => {
+            let socket = connection::udp::bind(address)?;
+            info!("Listening on udp://{}", socket.local_addr()?);
+
+            let listen = connection::udp::listen(
+                socket,
+                inbound.clone(),
+                limits,
+                guard.clone(),
+                bans.clone(),
+            );
+            tokio::spawn(listen);
+        }
+    }
+
+    Ok(())
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Error> {
+async fn main() -> Result<(), StartupError> {
+    let mut console = Console::open();
+
+    let mut logger = env_logger::Builder::from_env(Env::default().default_filter_or("info"));
+    if let Some(target) = console.as_mut().and_then(Console::log_target) {
+        logger.target(target);
+    }
+    logger.init();
+
     let args = Args::parse();
+    let config = args.load().inspect_err(|error| match &args.config {
+        Some(path) => error!("Failed to load {}: {error}", path.display()),
+        None => error!("{error}"),
+    })?;
+
+    let bans = BanList::load(&config.bans).inspect_err(|error| {
+        error!(
+            "Failed to load the ban list from {}: {error}",
+            config.bans.display()
+        );
+    })?;
+    let bans = Arc::new(RwLock::new(bans));
+
+    let (inbound, receiver) = mpsc::channel(server::INBOUND_CAPACITY);
 
-    let mut players = Vec::with_capacity(args.count);
+    let limits = Limits {
+        messages_per_second: config.max_messages_per_second.into(),
+        bytes_per_second: config.max_bytes_per_second.into(),
+        max_message_size: config.max_message_size,
+    };
 
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", args.port)).await?;
-    while players.len() < args.count {
-        let (socket, _) = listener.accept().await?;
+    let (guard, flushed) = shutdown::flush_guard();
+
+    // Every listener hands its clients to the same game loop.
+    for &address in &config.bind {
+        if let Err(error) = listen(address, config.transport, &inbound, limits, &guard, &bans) {
+            error!("Failed to listen on {address}: {error}");
+
+            return Err(error.into());
+        }
+    }
+
+    // Only the listeners and their connections hold on to the guard from here on.
+    drop(guard);
+
+    if let Some(password) = &config.rcon.password {
+        let logins = rcon::Logins::default();
+
+        for &address in &config.rcon.bind {
+            let listener = connection::tcp::bind(address).inspect_err(|error| {
+                error!("Failed to open the remote console on {address}: {error}");
+            })?;
+            info!("Remote console on tcp://{}", listener.local_addr()?);
+
+            let listen = rcon::listen(listener, inbound.clone(), password.clone(), logins.clone());
+            tokio::spawn(listen);
+        }
+    }
+
+    let terminal = console.and_then(|console| {
+        console
+            .spawn(inbound.clone())
+            .inspect_err(|error| warn!("Failed to start the console: {error}"))
+            .ok()
+    });
+
+    #[cfg(unix)]
+    tokio::spawn(reload(
+        args,
+        config.password.clone(),
+        inbound.clone(),
+        bans.clone(),
+    ));
+
+    let console = terminal.clone();
+    tokio::spawn(async move {
+        match shutdown::requested().await {
+            Ok(signal) => {
+                // The console may not get to print what the server says on its way out.
+                if let Some(console) = console {
+                    console.release();
+                }
+
+                let reason = format!("received {signal}");
+                let _ = inbound.send(Inbound::Shutdown { reason }).await;
+            }
+            Err(error) => warn!("Failed to listen for shutdown signals: {error}"),
+        }
+    });
+
+    info!(
+        "{} is running {:?} on {} at {} ticks per second",
+        config.hostname, config.mode, config.map, config.tick_rate
+    );
+    if config.password.is_some() {
+        info!("Players need the password to join");
+    }
+
+    let settings = Settings {
+        hostname: config.hostname,
+        map: config.map,
+        mode: config.mode,
+        password: config.password,
+        tick_rate: config.tick_rate,
+        players_allowed: config.min_players..=config.max_players,
+        countdown: Duration::from_secs(config.countdown),
+        round_time: Some(Duration::from_secs(config.round.time_limit))
+            .filter(|time| !time.is_zero()),
+        grace_period: Duration::from_secs(config.grace_period),
+        authority: config.movement_authority,
+        violation_response: config.movement_violation,
+        encoding: config.encoding,
+    };
+
+    let mut server = Server::new(receiver, settings, bans);
+    server.run().await;
+
+    // Dropping the game loop closes every connection once it has sent what it was given.
+    drop(server);
+    if !flushed.wait(FLUSH_TIMEOUT).await {
+        warn!("Gave up on connections that did not finish sending within {FLUSH_TIMEOUT:?}");
+    }
 
-        let player = Player::new(players.len(), socket).await?;
-        players.push(player);
+    if let Some(terminal) = terminal {
+        terminal.release();
     }
 
-    let mut server = Server::new(players);
-    server.run().await?;
+    info!("Goodbye!");
 
     Ok(())
 }
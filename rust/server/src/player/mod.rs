@@ -1,80 +1,483 @@
-use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
 
-use crate::player::position::Position;
+use clap::ValueEnum;
+use protocol::armor::{Armor, BASIC_HELMET};
+use protocol::message::{Encoding, ServerMessage};
+use protocol::movement::{Body, Buttons, Movement, UserCommand};
+use protocol::position::Position;
+use protocol::snapshot::PlayerState;
+use protocol::weapon::{Weapon, AK47};
+use serde::Deserialize;
 
+use crate::connection::Connection;
 use crate::Error;
 
-pub mod position;
+pub use validation::{Response, Violation};
 
-#[derive(Debug, Serialize, Deserialize)]
+use validation::Flight;
+
+mod validation;
+
+/// The health every player spawns with.
+pub const MAX_HEALTH: f64 = 100.0;
+
+/// How much sooner than their weapon allows a shot may arrive, since messages sent at an even
+/// pace do not necessarily arrive at one.
+const FIRE_JITTER: Duration = Duration::from_millis(20);
+
+/// How far the position a player predicted may stray from where the server moved them before
+/// they are put back.
+const MAX_DRIFT: f64 = 0.25;
+
+/// How long after putting a player back they are left alone, so the commands they sent before
+/// hearing about it do not set off another correction.
+const CORRECTION_COOLDOWN: Duration = Duration::from_millis(500);
+
+/// How many commands a player may get ahead of the simulation before the oldest are dropped.
+const MAX_QUEUED_COMMANDS: usize = 8;
+
+/// How many ticks in a row a late player's last command is repeated, before they stand still.
+const MAX_REPEATED_COMMANDS: u32 = 8;
+
+/// Who decides where players move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Authority {
+    /// The server simulates movement from the commands players send.
+    Server,
+    /// Players report where they ended up, and the server takes their word for it.
+    Client,
+}
+
+#[derive(Debug)]
 pub struct Player {
-    id: Option<usize>,
-    #[serde(skip)]
-    socket: Option<TcpStream>,
+    id: usize,
+    /// The secret that lets the player take their place back after losing their connection.
+    token: String,
+    /// The connection the player is currently playing over, if any.
+    connection: Option<Connection>,
+    name: String,
 
     health: f64,
-    position: Position,
+    armor: Armor,
+    /// The durability left on the player's armor.
+    durability: f64,
+    body: Body,
+
+    /// Commands waiting to be simulated, oldest first.
+    commands: VecDeque<UserCommand>,
+    /// The last command that was simulated, repeated when the next one is late.
+    last_command: Option<UserCommand>,
+    /// How many ticks in a row the player's command has been late.
+    late: u32,
+    /// How many ticks have passed since the player's last move was taken, which is all the time
+    /// they had to make the next one.
+    since_move: u64,
+    /// The player's time in the air as of their last move, if it left them there.
+    flight: Option<Flight>,
+    /// Whether the player's last command predicted a position too far from where it took them.
+    drifted: bool,
+    /// When the player may be put back again, after their last correction.
+    next_correction: Instant,
+    /// How long messages take to reach the player and come back, smoothed over recent pings.
+    round_trip: Option<Duration>,
+
+    weapon: Weapon,
+    ammo: u32,
+    /// When the player may fire again, after their previous shot or reload.
+    next_shot: Instant,
+    /// When the player last died, unless they have respawned since.
+    died: Option<Instant>,
+    kills: u32,
+    deaths: u32,
+
+    /// The tick of the newest snapshot the player has acknowledged.
+    acked: Option<u64>,
+    /// Whether the player wants the match to start.
+    ready: bool,
 }
 
 impl Player {
-    pub async fn new(id: usize, mut socket: TcpStream) -> Result<Self, Error> {
-        let mut buffer = [0; 1024];
-        socket.read(&mut buffer).await?;
+    pub fn new(id: usize, connection: Connection, name: String, position: Position) -> Self {
+        Self {
+            id,
+            token: format!("{:032x}", rand::random::<u128>()),
+            connection: Some(connection),
+            name,
+            health: MAX_HEALTH,
+            armor: BASIC_HELMET,
+            durability: BASIC_HELMET.max_durability,
+            body: Body::new(position),
 
-        let size = buffer.iter().position(|&x| x == 0).unwrap_or(buffer.len());
+            commands: VecDeque::new(),
+            last_command: None,
+            late: 0,
+            since_move: 0,
+            flight: None,
+            drifted: false,
+            next_correction: Instant::now(),
+            round_trip: None,
 
-        let player = serde_json::from_slice(&buffer[..size])?;
+            weapon: AK47,
+            ammo: AK47.max_ammo,
+            next_shot: Instant::now(),
+            died: None,
+            kills: 0,
+            deaths: 0,
 
-        Ok(Self {
-            id: Some(id),
-            socket: Some(socket),
-            ..player
-        })
+            acked: None,
+            ready: false,
+        }
     }
 
     pub fn id(&self) -> usize {
-        self.id.unwrap()
+        self.id
     }
 
-    pub fn socket(&self) -> &TcpStream {
-        self.socket.as_ref().unwrap()
+    pub fn token(&self) -> &str {
+        &self.token
     }
 
-    pub fn socket_mut(&mut self) -> &mut TcpStream {
-        self.socket.as_mut().unwrap()
+    /// Whether the player is currently playing over the connection with the given id.
+    pub fn is_connected_via(&self, connection: usize) -> bool {
+        self.connection.as_ref().map(Connection::id) == Some(connection)
+    }
+
+    /// Moves the player onto a new connection, dropping the old one if there still is one.
+    pub fn attach(&mut self, connection: Connection) {
+        self.connection = Some(connection);
+        self.acked = None;
+        self.round_trip = None;
+
+        // The new connection counts its ticks from scratch.
+        self.commands.clear();
+        self.last_command = None;
+    }
+
+    /// Lets go of the player's connection, keeping everything else.
+    pub fn detach(&mut self) -> Option<Connection> {
+        self.connection.take()
+    }
+
+    /// Where the player is connecting from, unless they are away.
+    pub fn ip(&self) -> Option<IpAddr> {
+        self.connection.as_ref().map(Connection::ip)
+    }
+
+    /// The encoding the player's connection agreed on.
+    pub fn encoding(&self) -> Encoding {
+        self.connection
+            .as_ref()
+            .map_or(Encoding::Json, |connection| connection.encoding().get())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
     }
 
     pub fn position(&self) -> &Position {
-        &self.position
+        &self.body.position
+    }
+
+    pub fn state(&self) -> PlayerState {
+        PlayerState {
+            id: self.id(),
+            health: self.health,
+            armor: self.durability,
+            alive: self.is_alive(),
+            position: self.body.position,
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.health > 0.0
     }
 
-    pub fn state(&self) -> Result<String, Error> {
-        serde_json::to_string(self).map_err(Into::into)
+    pub fn died(&self) -> Option<Instant> {
+        self.died
     }
 
-    pub async fn request(&mut self) -> Result<(), Error> {
-        // Ask the player for their new position.
-        self.socket_mut().write_all(b"GetState").await?;
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
 
-        let mut buffer = [0; 1024];
-        let size = self.socket_mut().read(&mut buffer).await?;
+    pub fn set_ready(&mut self, ready: bool) {
+        self.ready = ready;
+    }
+
+    pub fn acked(&self) -> Option<u64> {
+        self.acked
+    }
+
+    /// Records that the player has the snapshot of `tick`, unless they already have a newer one.
+    pub fn acknowledge(&mut self, tick: u64) {
+        self.acked = self.acked.max(Some(tick));
+    }
+
+    pub fn round_trip(&self) -> Option<Duration> {
+        self.round_trip
+    }
+
+    /// Takes a pong into account, with a bit of weight so a single slow one does not count for
+    /// much.
+    pub fn measure_round_trip(&mut self, sample: Duration) {
+        self.round_trip = Some(match self.round_trip {
+            Some(round_trip) => (round_trip * 7 + sample) / 8,
+            None => sample,
+        });
+    }
 
-        // Update the player state.
-        let state: Self = serde_json::from_slice(&buffer[..size])?;
-        *self = Self {
-            id: self.id,
-            socket: self.socket.take(),
-            ..state
+    pub fn weapon(&self) -> &Weapon {
+        &self.weapon
+    }
+
+    pub fn kills(&self) -> u32 {
+        self.kills
+    }
+
+    pub fn deaths(&self) -> u32 {
+        self.deaths
+    }
+
+    pub fn add_kill(&mut self) {
+        self.kills += 1;
+    }
+
+    /// Forgets the player's kills and deaths, for a new match.
+    pub fn reset_score(&mut self) {
+        self.kills = 0;
+        self.deaths = 0;
+    }
+
+    /// Queues a command for simulation, unless it is older than one we already have.
+    pub fn queue(&mut self, command: UserCommand) {
+        let newest = self.commands.back().or(self.last_command.as_ref());
+        if newest.is_some_and(|newest| command.tick <= newest.tick) {
+            return;
+        }
+
+        if self.commands.len() == MAX_QUEUED_COMMANDS {
+            self.commands.pop_front();
+        }
+        self.commands.push_back(command);
+    }
+
+    /// Moves the player according to their next command, or their last one if the next is late.
+    ///
+    /// Moves reported by the player are checked first, and only made if they are possible or the
+    /// response to violations is to let them through.
+    pub fn simulate(
+        &mut self,
+        authority: Authority,
+        response: Response,
+        movement: &Movement,
+        delta: f64,
+        floor: f64,
+    ) -> Result<(), Violation> {
+        self.drifted = false;
+
+        // Commands lost on the way leave a gap, but not one big enough to hide a teleport in.
+        self.since_move = (self.since_move + 1).min(MAX_QUEUED_COMMANDS as u64);
+
+        let mut command = match self.commands.pop_front() {
+            Some(command) => {
+                self.late = 0;
+                self.last_command = Some(command);
+
+                command
+            }
+            None => {
+                let Some(command) = self.last_command else {
+                    return Ok(());
+                };
+
+                self.late += 1;
+                command
+            }
         };
 
-        Ok(())
+        // Someone who stopped sending commands should not keep running forever.
+        if self.late > MAX_REPEATED_COMMANDS {
+            command.buttons = Buttons::default();
+        }
+
+        // The dead stay where they fell.
+        if !self.is_alive() {
+            return Ok(());
+        }
+
+        if authority == Authority::Server {
+            movement.simulate(&mut self.body, command.buttons, delta, floor);
+
+            // A prediction only strays this far when the client missed a correction or a respawn,
+            // or its simulation disagrees with ours.
+            self.drifted =
+                self.late == 0 && command.position.distance_to(&self.body.position) > MAX_DRIFT;
+
+            return Ok(());
+        }
+
+        // A repeated command was already judged when it first arrived.
+        if self.late > 0 {
+            return Ok(());
+        }
+
+        // The time the move took is counted in our ticks, as the client's could claim anything.
+        let elapsed = self.since_move as f64 * delta;
+
+        let result = validation::check(
+            movement,
+            &self.body.position,
+            &command.position,
+            elapsed,
+            floor,
+            self.flight,
+        );
+        if result.is_ok() || response == Response::Warn {
+            self.body.position = command.position;
+            self.since_move = 0;
+        }
+
+        match result {
+            Ok(flight) => {
+                self.flight = flight;
+
+                Ok(())
+            }
+            Err(violation) => {
+                // Wherever the player is left, they have lost any right to be rising.
+                self.flight = None;
+
+                Err(violation)
+            }
+        }
+    }
+
+    /// Whether the player should be told where they really are, since their own idea of it has
+    /// strayed. Only comes up again once the last correction has had time to arrive.
+    pub fn needs_correction(&mut self, now: Instant) -> bool {
+        if !self.drifted || now < self.next_correction {
+            return false;
+        }
+
+        self.next_correction = now + CORRECTION_COOLDOWN;
+
+        true
     }
 
-    pub async fn inform(&mut self, data: &[u8]) -> Result<(), Error> {
-        self.socket_mut().write_all(data).await?;
+    /// Fires the player's weapon if they are able to, reloading once it runs dry.
+    pub fn fire(&mut self, now: Instant) -> bool {
+        if !self.is_alive() || now + FIRE_JITTER < self.next_shot {
+            return false;
+        }
+
+        self.ammo -= 1;
+
+        let mut delay = self.weapon.fire_interval;
+        if self.ammo == 0 {
+            self.ammo = self.weapon.max_ammo;
+            delay = self.weapon.reload_time;
+        }
+        // Counting from when the shot was due, rather than when it arrived, means early shots
+        // leave less time for the next one and never add up to a faster rate of fire.
+        self.next_shot = self.next_shot.max(now) + Duration::from_secs_f64(delay);
+
+        true
+    }
+
+    pub fn health(&self) -> f64 {
+        self.health
+    }
+
+    /// Brings the player back to full health at `position`.
+    pub fn respawn(&mut self, position: Position) {
+        self.health = MAX_HEALTH;
+        self.durability = self.armor.max_durability;
+        self.body = Body::new(position);
+        self.flight = None;
+        self.ammo = self.weapon.max_ammo;
+        self.next_shot = Instant::now();
+        self.died = None;
+    }
+
+    /// Deals the damage of a shot from `weapon`, returning whether it killed the player.
+    ///
+    /// Their only armor is a helmet, so only shots to the head go through it, unless they kill
+    /// outright.
+    pub fn shoot(&mut self, weapon: &Weapon, distance: f64, headshot: bool) -> bool {
+        let damage = weapon.damage_at(distance);
+        let damage = match headshot.then(|| weapon.headshot_damage(damage)) {
+            None => damage,
+            Some(Some(damage)) => self.armor.absorb(damage, &mut self.durability),
+            Some(None) => self.health,
+        };
+        self.health -= damage.min(self.health);
+
+        let killed = !self.is_alive();
+        if killed {
+            self.deaths += 1;
+            self.died = Some(Instant::now());
+        }
+
+        killed
+    }
+
+    pub fn inform(&self, message: ServerMessage) -> Result<(), Error> {
+        match &self.connection {
+            Some(connection) => connection.send(message),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::weapon::HeadshotAction;
+
+    use super::*;
+
+    fn player() -> Player {
+        let (connection, _) = Connection::new("127.0.0.1:7512".parse().unwrap());
+
+        Player::new(0, connection, "player".to_string(), Position::ZERO)
+    }
+
+    #[test]
+    fn headshots_that_kill_go_through_the_helmet() {
+        let mut player = player();
+
+        assert!(player.shoot(&AK47, 10.0, true));
+        assert_eq!(player.health(), 0.0);
+        assert_eq!(player.deaths(), 1);
+        assert!(player.died().is_some());
+
+        player.respawn(Position::ZERO);
+        assert_eq!(player.health(), MAX_HEALTH);
+        assert_eq!(player.died(), None);
+    }
+
+    #[test]
+    fn body_shots_miss_the_helmet() {
+        let mut player = player();
+
+        assert!(!player.shoot(&AK47, 10.0, false));
+        assert_eq!(player.health(), MAX_HEALTH - AK47.max_damage);
+        assert_eq!(player.state().armor, BASIC_HELMET.max_durability);
+    }
+
+    #[test]
+    fn the_helmet_soaks_up_headshots() {
+        let mut player = player();
+        let weapon = Weapon {
+            on_headshot: HeadshotAction::DoubleDamage,
+            ..AK47
+        };
 
-        Ok(())
+        // Twice the damage, a tenth of which the helmet takes.
+        assert!(!player.shoot(&weapon, 10.0, true));
+        assert_eq!(player.health(), MAX_HEALTH - 45.0);
+        assert_eq!(player.state().armor, BASIC_HELMET.max_durability - 5.0);
     }
 }
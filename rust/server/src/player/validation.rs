@@ -0,0 +1,287 @@
+use clap::ValueEnum;
+use protocol::movement::Movement;
+use protocol::position::Position;
+use serde::Deserialize;
+use thiserror::Error;
+
+/// How much further than the rules allow a move may go, to make up for rounding on the client.
+const TOLERANCE: f64 = 1.1;
+/// The smallest difference worth calling a violation.
+const EPSILON: f64 = 0.01;
+/// How much longer than their client a player may seem to have been in the air, since their
+/// time is counted in our ticks and late commands make for ticks the client never had.
+const AIRTIME_SLACK: f64 = 0.1;
+
+/// What happens to a player who reports a move they could not have made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Response {
+    /// Keep them where they were and tell them so.
+    Correct,
+    /// Let the move through, but log it.
+    Warn,
+    /// Disconnect them.
+    Kick,
+}
+
+/// A reported move that breaks the rules of movement.
+#[derive(Debug, Clone, Copy, PartialEq, Error)]
+pub enum Violation {
+    #[error("moved {distance:.2} units sideways in {elapsed:.3}s, which allows for {allowed:.2}")]
+    TooFast {
+        distance: f64,
+        allowed: f64,
+        elapsed: f64,
+    },
+    #[error("rose {distance:.2} units in {elapsed:.3}s, which allows for {allowed:.2}")]
+    RoseTooFast {
+        distance: f64,
+        allowed: f64,
+        elapsed: f64,
+    },
+    #[error("got {height:.2} units off the ground, while jumps reach {allowed:.2}")]
+    TooHigh { height: f64, allowed: f64 },
+    #[error("sank {depth:.2} units into the ground")]
+    BelowFloor { depth: f64 },
+    #[error("was {height:.2} units up after {time:.3}s in the air, where gravity allows for {allowed:.2}")]
+    Floating {
+        height: f64,
+        allowed: f64,
+        time: f64,
+    },
+}
+
+/// A player's time off the ground, which gravity allows to go only so high for so long.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Flight {
+    /// The height the player left from.
+    origin: f64,
+    /// How fast the player may have been rising when they left.
+    speed: f64,
+    /// How long the player has been in the air.
+    time: f64,
+}
+
+impl Flight {
+    /// The highest the player can be at this point of their flight.
+    fn ceiling(&self, movement: &Movement) -> f64 {
+        let falling = (self.time - AIRTIME_SLACK).max(0.0);
+
+        self.origin + self.speed * TOLERANCE * self.time
+            - 0.5 * movement.gravity * falling * falling
+    }
+
+    /// Whether the player, `height` above the floor `elapsed` seconds ago, could have reached it
+    /// since.
+    fn could_land(&self, movement: &Movement, height: f64, elapsed: f64) -> bool {
+        let speed = movement.gravity * (self.time - elapsed) - self.speed;
+
+        speed * elapsed + 0.5 * movement.gravity * elapsed * elapsed >= height - EPSILON
+    }
+}
+
+/// Checks that a player could have moved from `from` to `to` in `elapsed` seconds, standing on a
+/// floor at the given height.
+///
+/// `flight` is how the player got to `from` if they are in the air, and the flight they are on
+/// after the move is returned, if it leaves them in the air.
+pub fn check(
+    movement: &Movement,
+    from: &Position,
+    to: &Position,
+    elapsed: f64,
+    floor: f64,
+    flight: Option<Flight>,
+) -> Result<Option<Flight>, Violation> {
+    // Holding two movement keys at once moves along both axes at full speed.
+    let distance = (to.x - from.x).hypot(to.z - from.z);
+    let allowed = movement.run_speed * std::f64::consts::SQRT_2 * elapsed * TOLERANCE;
+    if distance > allowed + EPSILON {
+        return Err(Violation::TooFast {
+            distance,
+            allowed,
+            elapsed,
+        });
+    }
+
+    let distance = to.y - from.y;
+    let allowed = movement.jump_force * elapsed * TOLERANCE;
+    if distance > allowed + EPSILON {
+        return Err(Violation::RoseTooFast {
+            distance,
+            allowed,
+            elapsed,
+        });
+    }
+
+    // Falling from higher up, like a spawn point, is fine, but rising beyond a jump is not.
+    let height = to.y - floor;
+    let allowed = movement.jump_height() * TOLERANCE;
+    if height > allowed + EPSILON && distance > 0.0 {
+        return Err(Violation::TooHigh { height, allowed });
+    }
+
+    let depth = floor - to.y;
+    if depth > EPSILON {
+        return Err(Violation::BelowFloor { depth });
+    }
+
+    if to.y <= floor + EPSILON {
+        return Ok(None);
+    }
+
+    let mut flight = match flight {
+        // Every jump starts from the floor.
+        _ if from.y <= floor + EPSILON => Flight {
+            origin: from.y,
+            speed: movement.jump_force,
+            time: 0.0,
+        },
+        Some(flight) => flight,
+        // Without knowing how the player got up there, all they can do is fall.
+        None => Flight {
+            origin: from.y,
+            speed: 0.0,
+            time: 0.0,
+        },
+    };
+    flight.time += elapsed;
+
+    // Lost commands may hide a landing, and the jump right after it, which is then where the
+    // player's flight starts.
+    let jump = Flight {
+        origin: floor,
+        speed: movement.jump_force,
+        time: elapsed,
+    };
+    if flight.could_land(movement, from.y - floor, elapsed) && to.y <= jump.ceiling(movement) {
+        flight = jump;
+    }
+
+    let ceiling = flight.ceiling(movement);
+    if to.y > ceiling + EPSILON {
+        return Err(Violation::Floating {
+            height: to.y - floor,
+            allowed: ceiling - floor,
+            time: flight.time,
+        });
+    }
+
+    Ok(Some(flight))
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::movement::{Body, Buttons, DEFAULT_MOVEMENT};
+
+    use super::*;
+
+    const DELTA: f64 = 1.0 / 60.0;
+
+    /// Where a player holding `buttons` from `start` is after each tick, as the client would
+    /// predict it, starting with `start`. Jump is only pressed on the first tick, unless `hop`.
+    fn path(start: Position, buttons: Buttons, ticks: usize, hop: bool) -> Vec<Position> {
+        let mut body = Body::new(start);
+
+        let moves = (0..ticks).map(|tick| {
+            let mut buttons = buttons;
+            if tick > 0 && !hop {
+                buttons.0 &= !Buttons::JUMP.0;
+            }
+
+            DEFAULT_MOVEMENT.simulate(&mut body, buttons, DELTA, 0.0);
+            body.position
+        });
+
+        [start].into_iter().chain(moves).collect()
+    }
+
+    fn running_jump() -> Buttons {
+        let mut buttons = Buttons::FORWARD;
+        buttons.insert(Buttons::RIGHT);
+        buttons.insert(Buttons::JUMP);
+
+        buttons
+    }
+
+    /// Checks every `step`th position along `path` as one move taking `elapsed` seconds,
+    /// returning the flight the player ends up on.
+    fn follow(
+        path: &[Position],
+        step: usize,
+        elapsed: f64,
+        mut flight: Option<Flight>,
+    ) -> Result<Option<Flight>, Violation> {
+        let mut from = path[0];
+        for to in path.iter().skip(step).step_by(step) {
+            flight = check(&DEFAULT_MOVEMENT, &from, to, elapsed, 0.0, flight)?;
+            from = *to;
+        }
+
+        Ok(flight)
+    }
+
+    #[test]
+    fn accepts_a_running_jump() {
+        let path = path(Position::ZERO, running_jump(), 90, false);
+
+        // The player is back on the ground by the end of it.
+        assert_eq!(follow(&path, 1, DELTA, None), Ok(None));
+    }
+
+    #[test]
+    fn accepts_moves_spanning_lost_commands() {
+        let path = path(Position::ZERO, running_jump(), 90, false);
+
+        for step in 2..=8 {
+            assert!(follow(&path, step, step as f64 * DELTA, None).is_ok());
+        }
+    }
+
+    #[test]
+    fn accepts_jumping_again_on_landing_across_lost_commands() {
+        let path = path(Position::ZERO, running_jump(), 180, true);
+
+        for step in 1..=8 {
+            assert!(follow(&path, step, step as f64 * DELTA, None).is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_moves_squeezed_into_fewer_ticks() {
+        let path = path(Position::ZERO, running_jump(), 90, false);
+
+        assert!(matches!(
+            follow(&path, 3, DELTA, None),
+            Err(Violation::TooFast { .. })
+        ));
+    }
+
+    #[test]
+    fn accepts_a_fall_from_a_spawn_point() {
+        let path = path(Position::new(0.0, 10.0, 0.0), Buttons::FORWARD, 120, false);
+
+        assert_eq!(follow(&path, 1, DELTA, None), Ok(None));
+    }
+
+    #[test]
+    fn rejects_hovering_at_the_top_of_a_jump() {
+        let mut path = path(Position::ZERO, Buttons::JUMP, 30, false);
+        let top = *path.last().unwrap();
+        path.extend([top; 60]);
+
+        assert!(matches!(
+            follow(&path, 1, DELTA, None),
+            Err(Violation::Floating { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_jumping_again_in_midair() {
+        let from = Position::new(0.0, 0.5, 0.0);
+        let to = Position::new(0.0, 0.55, 0.0);
+
+        let result = check(&DEFAULT_MOVEMENT, &from, &to, DELTA, 0.0, None);
+        assert!(matches!(result, Err(Violation::Floating { .. })));
+    }
+}
@@ -0,0 +1,159 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use protocol::message::{self, Encoding};
+use protocol::rcon::{RconRequest, RconResponse};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time;
+
+use crate::codec;
+use crate::connection::{password_matches, Attempts};
+use crate::server::Inbound;
+use crate::Error;
+
+/// How long an admin has to log in before they are hung up on.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The failed logins each address can still afford, shared by every listener.
+pub type Logins = Arc<Mutex<Attempts>>;
+
+/// Serves the remote console until the game loop goes away.
+pub async fn listen(
+    listener: TcpListener,
+    inbound: mpsc::Sender<Inbound>,
+    password: String,
+    logins: Logins,
+) {
+    loop {
+        let result = tokio::select! {
+            result = listener.accept() => result,
+            _ = inbound.closed() => return,
+        };
+
+        let (socket, address) = match result {
+            Ok(accepted) => accepted,
+            Err(error) => {
+                warn!("Failed to accept a remote console connection: {error}");
+
+                continue;
+            }
+        };
+
+        let session = Session {
+            socket,
+            address,
+            inbound: inbound.clone(),
+        };
+        tokio::spawn(session.run(password.clone(), logins.clone()));
+    }
+}
+
+/// A single admin's connection to the remote console.
+struct Session {
+    socket: TcpStream,
+    address: SocketAddr,
+    inbound: mpsc::Sender<Inbound>,
+}
+
+impl Session {
+    async fn run(mut self, password: String, logins: Logins) {
+        let result = match self.log_in(&password, &logins).await {
+            Ok(true) => self.serve().await,
+            Ok(false) => Ok(()),
+            Err(error) => Err(error),
+        };
+
+        match result {
+            Ok(()) => info!("Remote console session with {} ended", self.address),
+            Err(Error::Io(error)) if error.kind() == ErrorKind::UnexpectedEof => {
+                info!("Remote console session with {} ended", self.address)
+            }
+            Err(error) => warn!(
+                "Remote console session with {} failed: {error}",
+                self.address
+            ),
+        }
+    }
+
+    /// Checks the password the admin starts with, returning whether they got it right.
+    async fn log_in(&mut self, password: &str, logins: &Logins) -> Result<bool, Error> {
+        let request = time::timeout(LOGIN_TIMEOUT, self.receive())
+            .await
+            .map_err(|_| Error::TimedOut)??;
+        let RconRequest::Login { password: given } = request else {
+            self.respond(&RconResponse::Error("Log in first".to_string()))
+                .await?;
+
+            return Ok(false);
+        };
+
+        let ip = self.address.ip();
+        let now = Instant::now();
+
+        let error = {
+            let mut logins = logins.lock().expect("logins are never poisoned");
+
+            if logins.is_locked_out(ip, now) {
+                Some("Too many failed logins, try again later")
+            } else if password_matches(&given, password) {
+                None
+            } else {
+                logins.fail(ip, now);
+
+                Some("Wrong password")
+            }
+        };
+
+        if let Some(error) = error {
+            warn!(
+                "Turned down a remote console login from {}: {error}",
+                self.address
+            );
+            self.respond(&RconResponse::Error(error.to_string()))
+                .await?;
+
+            return Ok(false);
+        }
+
+        info!("{} logged in to the remote console", self.address);
+        self.respond(&RconResponse::LoggedIn).await?;
+
+        Ok(true)
+    }
+
+    /// Passes commands to the game loop until the admin hangs up.
+    async fn serve(&mut self) -> Result<(), Error> {
+        loop {
+            let response = match self.receive().await? {
+                RconRequest::Login { .. } => RconResponse::Error("Already logged in".to_string()),
+                RconRequest::Command(command) => {
+                    let (reply, response) = oneshot::channel();
+                    let command = Inbound::Command { command, reply };
+                    if self.inbound.send(command).await.is_err() {
+                        return Ok(());
+                    }
+
+                    response.await.unwrap_or_else(|_| {
+                        RconResponse::Error("The server is shutting down".to_string())
+                    })
+                }
+            };
+
+            self.respond(&response).await?;
+        }
+    }
+
+    async fn receive(&mut self) -> Result<RconRequest, Error> {
+        let frame = codec::read_frame(&mut self.socket).await?;
+
+        Ok(message::decode(&frame, Encoding::Json)?)
+    }
+
+    async fn respond(&mut self, response: &RconResponse) -> Result<(), Error> {
+        codec::write_message(&mut self.socket, response, Encoding::Json).await
+    }
+}
@@ -0,0 +1,324 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use log::{error, info, warn};
+use protocol::message::{Event, ServerMessage};
+use protocol::rcon::{BanTarget, Command, PlayerStatus, RconResponse, Status};
+
+use crate::bans::{self, Ban};
+use crate::error::Error;
+use crate::server::{Phase, Server, Settings};
+
+/// The server variables admins can read. Only some of them can be changed at runtime.
+const VARIABLES: [&str; 13] = [
+    "hostname",
+    "password",
+    "map",
+    "mode",
+    "tick_rate",
+    "min_players",
+    "max_players",
+    "countdown",
+    "time_limit",
+    "grace_period",
+    "movement_authority",
+    "movement_violation",
+    "encoding",
+];
+
+impl Server {
+    /// Carries out a command from the remote console.
+    pub(super) fn execute(&mut self, command: Command) -> RconResponse {
+        let result = match command {
+            Command::Status => return RconResponse::Status(self.status()),
+            Command::Kick { id } => self.kick(id, Error::Kicked),
+            Command::Ban {
+                target,
+                duration,
+                reason,
+            } => self.ban(target, duration, reason),
+            Command::Say { text } => {
+                info!("Admin: {text}");
+
+                self.broadcast(ServerMessage::Event(Event::Announcement { text }));
+                Ok(())
+            }
+            Command::ChangeLevel { map } => self.change_level(map),
+            Command::RestartRound => {
+                info!("An admin restarted the round");
+
+                self.restart_round();
+                self.broadcast(ServerMessage::Event(Event::RoundRestarted));
+                Ok(())
+            }
+            Command::Pause { paused } => self.pause(paused),
+            Command::Get { name } => {
+                return match self.settings.get(&name) {
+                    Ok(value) => RconResponse::Value { name, value },
+                    Err(error) => RconResponse::Error(error),
+                };
+            }
+            Command::Set { name, value } => self.settings.set(&name, &value).map(|()| {
+                info!("An admin changed {name}");
+            }),
+        };
+
+        match result {
+            Ok(()) => RconResponse::Done,
+            Err(error) => RconResponse::Error(error),
+        }
+    }
+
+    fn status(&self) -> Status {
+        let phase = match self.phase {
+            Phase::Lobby => "lobby".to_string(),
+            Phase::Countdown(start) => format!(
+                "starting in {:.0}s",
+                start
+                    .saturating_duration_since(Instant::now())
+                    .as_secs_f64()
+            ),
+            Phase::Live(started) => format!("live for {:.0}s", started.elapsed().as_secs_f64()),
+        };
+
+        let away = self.away.iter().map(|(player, _)| player);
+        let players = self
+            .players
+            .iter()
+            .chain(away)
+            .map(|player| PlayerStatus {
+                id: player.id(),
+                name: player.name().to_string(),
+                address: player.ip().map(|ip| ip.to_string()),
+                health: player.health(),
+                kills: player.kills(),
+                deaths: player.deaths(),
+            })
+            .collect();
+
+        Status {
+            hostname: self.settings.hostname.clone(),
+            map: self.settings.map.clone(),
+            phase,
+            paused: self.paused.is_some(),
+            tick: self.tick,
+            players,
+        }
+    }
+
+    /// Removes a player from the match, or drops the place of one who is away.
+    fn kick(&mut self, id: usize, error: Error) -> Result<(), String> {
+        if self.players.iter().any(|p| p.id() == id) {
+            self.remove(id, error);
+
+            return Ok(());
+        }
+
+        let Some(index) = self.away.iter().position(|(p, _)| p.id() == id) else {
+            return Err(format!("There is no player {id}"));
+        };
+
+        let (player, _) = self.away.remove(index);
+        info!("{} ({id}) lost their place: {error}", player.name());
+
+        Ok(())
+    }
+
+    fn ban(
+        &mut self,
+        target: BanTarget,
+        duration: Option<u64>,
+        reason: Option<String>,
+    ) -> Result<(), String> {
+        let (ip, name) = match target {
+            BanTarget::Player(id) => {
+                let away = self.away.iter().map(|(player, _)| player);
+                let Some(player) = self.players.iter().chain(away).find(|p| p.id() == id) else {
+                    return Err(format!("There is no player {id}"));
+                };
+
+                (player.ip(), Some(player.name().to_string()))
+            }
+            BanTarget::Address(ip) => (Some(ip.to_canonical()), None),
+            BanTarget::Name(name) if name.trim().is_empty() => {
+                return Err("The name cannot be empty".to_string())
+            }
+            BanTarget::Name(name) => (None, Some(name)),
+        };
+
+        let ban = Ban {
+            ip,
+            name,
+            reason,
+            expires: duration.map(|duration| bans::now().saturating_add(duration)),
+        };
+
+        let away = self.away.iter().map(|(player, _)| player);
+        let banned: Vec<_> = self
+            .players
+            .iter()
+            .chain(away)
+            .filter(|p| ban.covers(p.ip(), Some(p.name())))
+            .map(|p| p.id())
+            .collect();
+
+        info!("Banned {ban}");
+        if ban.ip.is_none() {
+            warn!("Nothing ties a name to a player, so they can come back under another one");
+        }
+        let save = self
+            .bans
+            .write()
+            .expect("the ban list is never poisoned")
+            .add(ban);
+
+        // Writing the file would hold up the match, and the ban holds until the server stops
+        // even if it could not be saved.
+        tokio::task::spawn_blocking(move || {
+            if let Err(error) = save.write() {
+                error!("Failed to save the ban list: {error}");
+            }
+        });
+
+        for id in banned {
+            self.kick(id, Error::Banned)?;
+        }
+
+        Ok(())
+    }
+
+    fn change_level(&mut self, map: String) -> Result<(), String> {
+        if map.trim().is_empty() {
+            return Err("The map cannot be empty".to_string());
+        }
+
+        info!("Changing the map to {map}");
+
+        self.settings.map = map.clone();
+        self.restart_round();
+        self.broadcast(ServerMessage::Event(Event::MapChanged { map }));
+
+        Ok(())
+    }
+
+    fn pause(&mut self, paused: bool) -> Result<(), String> {
+        match (self.paused, paused) {
+            (None, true) => self.paused = Some(Instant::now()),
+            (Some(since), false) => {
+                // The clocks of the match stand still while it is frozen.
+                let frozen = since.elapsed();
+                self.phase = match self.phase {
+                    Phase::Lobby => Phase::Lobby,
+                    Phase::Countdown(start) => Phase::Countdown(start + frozen),
+                    Phase::Live(started) => Phase::Live(started + frozen),
+                };
+
+                self.paused = None;
+            }
+            (Some(_), true) => return Err("The match is already paused".to_string()),
+            (None, false) => return Err("The match is not paused".to_string()),
+        }
+
+        info!(
+            "An admin {} the match",
+            if paused { "paused" } else { "unpaused" }
+        );
+        self.broadcast(ServerMessage::Event(Event::Paused { paused }));
+
+        Ok(())
+    }
+}
+
+impl Settings {
+    fn get(&self, name: &str) -> Result<String, String> {
+        let value = match name {
+            "hostname" => self.hostname.clone(),
+            "password" => self.password.clone().unwrap_or_default(),
+            "map" => self.map.clone(),
+            "mode" => format!("{:?}", self.mode).to_lowercase(),
+            "tick_rate" => self.tick_rate.to_string(),
+            "min_players" => self.players_allowed.start().to_string(),
+            "max_players" => self.players_allowed.end().to_string(),
+            "countdown" => self.countdown.as_secs().to_string(),
+            "time_limit" => self.round_time.unwrap_or_default().as_secs().to_string(),
+            "grace_period" => self.grace_period.as_secs().to_string(),
+            "movement_authority" => name_of(self.authority),
+            "movement_violation" => name_of(self.violation_response),
+            "encoding" => format!("{:?}", self.encoding).to_lowercase(),
+            _ => return Err(unknown(name)),
+        };
+
+        Ok(value)
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let (min, max) = self.players_allowed.clone().into_inner();
+
+        match name {
+            "hostname" if value.trim().is_empty() => {
+                return Err("hostname cannot be empty".to_string())
+            }
+            "hostname" => self.hostname = value.to_string(),
+            // Setting it to nothing opens the server to everyone.
+            "password" => self.password = Some(value.to_string()).filter(|p| !p.is_empty()),
+            "map" => return Err("Use changelevel to switch maps".to_string()),
+            "min_players" => {
+                let min = parse(value)?;
+                if min == 0 || min > max {
+                    return Err(format!("min_players must be between 1 and {max}"));
+                }
+
+                self.players_allowed = min..=max;
+            }
+            "max_players" => {
+                let max = parse(value)?;
+                if max < min {
+                    return Err(format!("max_players cannot be less than {min}"));
+                }
+
+                self.players_allowed = min..=max;
+            }
+            "countdown" => self.countdown = Duration::from_secs(parse(value)?),
+            "time_limit" => {
+                self.round_time = Some(Duration::from_secs(parse(value)?)).filter(|t| !t.is_zero())
+            }
+            "grace_period" => self.grace_period = Duration::from_secs(parse(value)?),
+            "movement_authority" => self.authority = ValueEnum::from_str(value, true)?,
+            "movement_violation" => self.violation_response = ValueEnum::from_str(value, true)?,
+            _ if VARIABLES.contains(&name) => {
+                return Err(format!(
+                    "{name} cannot be changed while the server is running"
+                ))
+            }
+            _ => return Err(unknown(name)),
+        }
+
+        Ok(())
+    }
+}
+
+fn parse<T>(value: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|error| format!("Invalid value {value:?}: {error}"))
+}
+
+/// The name a value goes by on the command line.
+fn name_of(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string())
+}
+
+fn unknown(name: &str) -> String {
+    format!(
+        "Unknown variable {name:?}, expected one of {}",
+        VARIABLES.join(", ")
+    )
+}
@@ -0,0 +1,243 @@
+use protocol::position::Position;
+use protocol::snapshot::PlayerState;
+
+/// The center of the `HeadCollider` sphere in `Player.tscn`, relative to the player.
+const HEAD_CENTER: Position = Position::new(0.0, 0.5, 0.0);
+const HEAD_RADIUS: f64 = 0.55;
+
+/// The `BodyCollider` capsule in `Player.tscn`, centered on the player.
+const BODY_RADIUS: f64 = 0.5;
+/// Half the distance between the centers of the capsule's caps.
+const BODY_HALF_HEIGHT: f64 = 0.5;
+
+/// Where the `Camera` sits in `Player.tscn`, relative to the player.
+const CAMERA_OFFSET: Position = Position::new(0.0, 0.65, 0.25);
+
+/// How far outside the head a hit may land and still count as a headshot, like on the client.
+const HEADSHOT_MARGIN: f64 = 0.1;
+
+/// A half-line shots travel along.
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    origin: Position,
+    /// Always of unit length.
+    direction: Position,
+}
+
+/// The first player a ray ran into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub id: usize,
+    pub distance: f64,
+    pub headshot: bool,
+}
+
+impl Ray {
+    /// The ray through the center of the view of a player standing at `position`, looking in the
+    /// direction given by `yaw` and `pitch` in radians.
+    pub fn from_view(position: Position, yaw: f64, pitch: f64) -> Self {
+        // Godot rotates around the X axis first, then around the Y axis, and looks down -Z.
+        let rotate = |v: Position| {
+            let (sin, cos) = pitch.sin_cos();
+            let v = Position::new(v.x, v.y * cos - v.z * sin, v.y * sin + v.z * cos);
+
+            let (sin, cos) = yaw.sin_cos();
+            Position::new(v.x * cos + v.z * sin, v.y, v.z * cos - v.x * sin)
+        };
+
+        Self {
+            origin: position + rotate(CAMERA_OFFSET),
+            direction: rotate(Position::new(0.0, 0.0, -1.0)),
+        }
+    }
+
+    fn at(&self, distance: f64) -> Position {
+        self.origin + self.direction * distance
+    }
+}
+
+/// Finds the first of `players` the ray hits within `range`, treating everyone as standing
+/// upright.
+pub fn trace<'a>(
+    ray: &Ray,
+    players: impl IntoIterator<Item = &'a PlayerState>,
+    range: f64,
+) -> Option<Hit> {
+    players
+        .into_iter()
+        .filter_map(|player| {
+            let head = player.position + HEAD_CENTER;
+
+            let distance = [
+                intersect_sphere(ray, head, HEAD_RADIUS),
+                intersect_capsule(ray, player.position),
+            ]
+            .into_iter()
+            .flatten()
+            .min_by(f64::total_cmp)?;
+
+            let headshot = ray.at(distance).distance_to(&head) - HEAD_RADIUS <= HEADSHOT_MARGIN;
+
+            Some(Hit {
+                id: player.id,
+                distance,
+                headshot,
+            })
+        })
+        .filter(|hit| hit.distance <= range)
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
+
+/// The distance along the ray to where it enters the sphere, if it does.
+fn intersect_sphere(ray: &Ray, center: Position, radius: f64) -> Option<f64> {
+    let offset = ray.origin - center;
+
+    let b = offset.dot(&ray.direction);
+    let c = offset.dot(&offset) - radius * radius;
+
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    // When the ray starts inside the sphere, it hits where it leaves.
+    let root = discriminant.sqrt();
+    [-b - root, -b + root].into_iter().find(|&t| t >= 0.0)
+}
+
+/// The distance along the ray to where it enters an upright body capsule centered on `center`.
+fn intersect_capsule(ray: &Ray, center: Position) -> Option<f64> {
+    let caps = [-BODY_HALF_HEIGHT, BODY_HALF_HEIGHT]
+        .map(|height| intersect_sphere(ray, center + Position::new(0.0, height, 0.0), BODY_RADIUS));
+
+    // The side of the capsule, as a vertical cylinder between the centers of its caps.
+    let (ox, oz) = (ray.origin.x - center.x, ray.origin.z - center.z);
+    let (dx, dz) = (ray.direction.x, ray.direction.z);
+
+    let a = dx * dx + dz * dz;
+    let b = ox * dx + oz * dz;
+    let c = ox * ox + oz * oz - BODY_RADIUS * BODY_RADIUS;
+
+    let discriminant = b * b - a * c;
+    let side = (a > f64::EPSILON && discriminant >= 0.0)
+        .then(|| (-b - discriminant.sqrt()) / a)
+        .filter(|&t| t >= 0.0 && (ray.at(t).y - center.y).abs() <= BODY_HALF_HEIGHT);
+
+    caps.into_iter()
+        .chain([side])
+        .flatten()
+        .min_by(f64::total_cmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_2;
+
+    use super::*;
+
+    // The player scene puts the head sphere's top at 1.05 and leaves the capsule at Godot's
+    // defaults, a radius of 0.5 and a height of 2, so the body spans -1 to 1.
+
+    fn player(id: usize, position: Position) -> PlayerState {
+        PlayerState {
+            id,
+            health: 100.0,
+            armor: 0.0,
+            alive: true,
+            position,
+        }
+    }
+
+    /// A ray from `origin` looking down -Z, towards a player at the origin.
+    fn ray(origin: Position) -> Ray {
+        Ray {
+            origin,
+            direction: Position::new(0.0, 0.0, -1.0),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn hits_the_head_before_the_body() {
+        let target = player(0, Position::default());
+
+        let hit = trace(&ray(Position::new(0.0, 0.9, 10.0)), [&target], 100.0).unwrap();
+
+        // The head is 0.4 above its center here, where it is wider than the capsule's cap.
+        assert_close(
+            hit.distance,
+            10.0 - (0.55f64.powi(2) - 0.4f64.powi(2)).sqrt(),
+        );
+        assert!(hit.headshot);
+    }
+
+    #[test]
+    fn hits_the_side_of_the_body() {
+        let target = player(0, Position::default());
+
+        let hit = trace(&ray(Position::new(0.0, -0.5, 10.0)), [&target], 100.0).unwrap();
+
+        assert_close(hit.distance, 9.5);
+        assert!(!hit.headshot);
+    }
+
+    #[test]
+    fn follows_the_outline_of_the_player() {
+        let target = player(0, Position::new(3.0, 2.0, -4.0));
+        let hits = |x: f64, y: f64| {
+            let origin = target.position + Position::new(x, y, 10.0);
+            trace(&ray(origin), [&target], 100.0).is_some()
+        };
+
+        // Above the head, below the feet, and either side of the body.
+        assert!(hits(0.0, 1.04));
+        assert!(!hits(0.0, 1.06));
+        assert!(hits(0.0, -0.99));
+        assert!(!hits(0.0, -1.01));
+        assert!(hits(0.49, 0.0));
+        assert!(!hits(0.51, 0.0));
+        assert!(hits(-0.49, 0.0));
+        assert!(!hits(-0.51, 0.0));
+    }
+
+    #[test]
+    fn hits_the_nearest_player_in_range() {
+        let near = player(1, Position::new(0.0, 0.0, 2.0));
+        let far = player(2, Position::default());
+        let shot = ray(Position::new(0.0, 0.0, 10.0));
+
+        let hit = trace(&shot, [&far, &near], 100.0).unwrap();
+        assert_eq!(hit.id, 1);
+
+        assert_eq!(trace(&shot, [&far], 100.0).map(|hit| hit.id), Some(2));
+        assert_eq!(trace(&shot, [&far], 9.0), None);
+    }
+
+    #[test]
+    fn shoots_from_the_camera() {
+        let target = player(0, Position::default());
+
+        // Level with the camera, 0.15 above the target's head center.
+        let ray = Ray::from_view(Position::new(0.0, 0.0, 10.0), 0.0, 0.0);
+        let hit = trace(&ray, [&target], 100.0).unwrap();
+        assert_close(
+            hit.distance,
+            10.25 - (0.55f64.powi(2) - 0.15f64.powi(2)).sqrt(),
+        );
+        assert!(hit.headshot);
+
+        // Turning a quarter left in Godot looks down -X.
+        let ray = Ray::from_view(Position::new(10.0, 0.0, -0.25), FRAC_PI_2, 0.0);
+        assert_eq!(trace(&ray, [&target], 100.0).map(|hit| hit.id), Some(0));
+
+        // Looking up misses.
+        let ray = Ray::from_view(Position::new(0.0, 0.0, 10.0), 0.0, 0.5);
+        assert_eq!(trace(&ray, [&target], 100.0), None);
+    }
+}
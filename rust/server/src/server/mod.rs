@@ -1,34 +1,929 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::ops::{ControlFlow, RangeInclusive};
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use protocol::message::{
+    ClientMessage, Encoding, Event, GameMode, Rejection, ServerMessage, MAX_CHAT_LENGTH,
+    MAX_NAME_LENGTH, PROTOCOL_VERSION,
+};
+use protocol::movement::DEFAULT_MOVEMENT;
+use protocol::position::Position;
+use protocol::rcon::{Command, RconResponse};
+use protocol::snapshot::Snapshot;
+use tokio::sync::mpsc::{self, error::TryRecvError};
+use tokio::sync::oneshot;
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::bans::{Ban, Bans};
+use crate::connection::{password_matches, Attempts, Connection};
+use crate::player::{Authority, Response};
+use crate::stats::STATS;
 use crate::{error::Error, Player};
+use hitscan::Ray;
+
+mod command;
+mod hitscan;
+
+/// How many events from connection tasks may queue up before they have to wait for the game loop.
+pub const INBOUND_CAPACITY: usize = 1024;
+
+/// How many past snapshots are kept around as baselines for deltas.
+const SNAPSHOT_HISTORY: usize = 64;
+
+/// Where players spawn, handed out in turn.
+const SPAWN_POINTS: [Position; 2] = [
+    Position::new(-1.25, 2.0, 0.0),
+    Position::new(1.25, 2.0, 0.0),
+];
+
+/// The height of a player standing on the ground of the arena.
+const FLOOR_HEIGHT: f64 = 1.5;
+
+/// How long the dead wait before they respawn, while the match is underway.
+const RESPAWN_DELAY: Duration = Duration::from_secs(3);
+
+/// How long a connection has to say hello before it is let go.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often players are pinged to measure their round trip.
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How much further back than their round trip a shot may be traced, for the jitter in it and
+/// the tick the client spent rendering.
+const UNLAG_SLACK: Duration = Duration::from_millis(50);
+/// How far back a shot may be traced at most, however slow the shooter's connection.
+const MAX_UNLAG: Duration = Duration::from_millis(250);
+
+/// Something a connection task wants the game loop to know about.
+#[derive(Debug)]
+pub enum Inbound {
+    Connected(Connection),
+    Message {
+        id: usize,
+        message: ClientMessage,
+    },
+    Disconnected {
+        id: usize,
+        error: Error,
+    },
+    /// The server has been asked to stop, for the given reason.
+    Shutdown {
+        reason: String,
+    },
+    /// Players need this password to join from now on, or none at all.
+    SetPassword(Option<String>),
+    /// An admin wants the server to do something, and is waiting for the answer.
+    Command {
+        command: Command,
+        reply: oneshot::Sender<RconResponse>,
+    },
+}
 
-use tokio::io::AsyncWriteExt;
+/// Where the match is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Waiting for enough players to ready up.
+    Lobby,
+    /// Everyone is ready, and the match starts at the given time unless someone changes their mind.
+    Countdown(Instant),
+    /// The match has been going since the given time.
+    Live(Instant),
+}
+
+/// How the server runs its matches.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub hostname: String,
+    pub map: String,
+    pub mode: GameMode,
+    /// The password players need to join with, if any.
+    pub password: Option<String>,
+    pub tick_rate: u32,
+    /// How many players are needed to start the match, and how many fit in it.
+    pub players_allowed: RangeInclusive<usize>,
+    pub countdown: Duration,
+    /// How long a round lasts before everyone goes back to the lobby, if there is a limit.
+    pub round_time: Option<Duration>,
+    /// How long a player who lost their connection keeps their place.
+    pub grace_period: Duration,
+    pub authority: Authority,
+    pub violation_response: Response,
+    /// The encoding the server would rather speak, if the client can. JSON is always an option.
+    pub encoding: Encoding,
+}
 
 #[derive(Debug)]
 pub struct Server {
     players: Vec<Player>,
+    /// Connections that have yet to say hello, with when they were accepted.
+    pending: HashMap<usize, (Connection, Instant)>,
+    /// Players that lost their connection and may still reconnect, with when they left.
+    away: Vec<(Player, Instant)>,
+    /// The wrong passwords each address can still try before it has to wait.
+    attempts: Attempts,
+    /// The addresses and names that admins have banned from joining.
+    bans: Bans,
+    next_id: usize,
+
+    settings: Settings,
+    phase: Phase,
+    /// When an admin froze the match, if they did.
+    paused: Option<Instant>,
+
+    inbound: mpsc::Receiver<Inbound>,
+
+    /// When the server started running.
+    started: Instant,
+    /// The number of the last simulated tick.
+    tick: u64,
+    /// How many ticks took longer than they were allowed to.
+    overruns: u64,
+    /// When players were last pinged.
+    pinged: Instant,
+    /// The most recent snapshots, oldest first.
+    history: VecDeque<Snapshot>,
+
+    /// Players whose messages could not be delivered, to be disconnected at the end of the tick.
+    failed: Vec<(usize, Error)>,
 }
 
 impl Server {
-    pub fn new(players: Vec<Player>) -> Self {
-        Self { players }
+    pub fn new(inbound: mpsc::Receiver<Inbound>, settings: Settings, bans: Bans) -> Self {
+        Self {
+            players: Vec::new(),
+            pending: HashMap::new(),
+            away: Vec::new(),
+            attempts: Attempts::default(),
+            bans,
+            next_id: 0,
+
+            settings,
+            phase: Phase::Lobby,
+            paused: None,
+
+            inbound,
+
+            started: Instant::now(),
+            tick: 0,
+            overruns: 0,
+            pinged: Instant::now(),
+            history: VecDeque::with_capacity(SNAPSHOT_HISTORY),
+
+            failed: Vec::new(),
+        }
     }
 
-    pub async fn run(&mut self) -> Result<(), Error> {
+    /// Runs the game loop until the server is asked to shut down.
+    pub async fn run(&mut self) {
+        let budget = Duration::from_secs(1) / self.settings.tick_rate;
+
+        let mut interval = time::interval(budget);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
         loop {
-            // First, request all players states.
-            for player in self.players.iter_mut() {
-                player.request().await?;
+            interval.tick().await;
+            let started = Instant::now();
+
+            // First, catch up on everything the connections sent since the last tick.
+            loop {
+                match self.inbound.try_recv() {
+                    Ok(inbound) => {
+                        if self.handle(inbound).is_break() {
+                            return;
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return,
+                }
+            }
+
+            // Then, advance the match and inform all players about the world.
+            if self.paused.is_none() {
+                self.advance_phase();
+            }
+            self.tick += 1;
+
+            if self.paused.is_none() {
+                self.simulate(budget.as_secs_f64());
+            }
+
+            let snapshot = Snapshot {
+                tick: self.tick,
+                players: self.players.iter().map(Player::state).collect(),
+            };
+            self.send_snapshot(snapshot);
+
+            if self.pinged.elapsed() >= PING_INTERVAL {
+                self.pinged = Instant::now();
+
+                let time = self.started.elapsed().as_millis() as u64;
+                self.broadcast(ServerMessage::Ping { time });
+            }
+
+            // Finally, let go of everyone who could not keep up or took too long to come back.
+            while let Some((id, error)) = self.failed.pop() {
+                self.remove(id, error);
             }
 
-            // Then, inform all players about the others states.
-            let states: Vec<_> = self
-                .players
+            self.away.retain(|(player, since)| {
+                let expired = since.elapsed() > self.settings.grace_period;
+                if expired {
+                    info!(
+                        "{} ({}) did not reconnect in time",
+                        player.name(),
+                        player.id()
+                    );
+                }
+
+                !expired
+            });
+
+            let silent: Vec<_> = self
+                .pending
                 .iter()
-                .map(|p| p.state())
-                .collect::<Result<_, _>>()?;
+                .filter(|(_, (_, since))| since.elapsed() > HANDSHAKE_TIMEOUT)
+                .map(|(&id, _)| id)
+                .collect();
+            for id in silent {
+                if let Some((connection, _)) = self.pending.remove(&id) {
+                    refuse(connection, Error::TimedOut);
+                }
+            }
+
+            let elapsed = started.elapsed();
+            if elapsed > budget {
+                self.overruns += 1;
+
+                warn!(
+                    "Tick {} took {elapsed:?}, over its budget of {budget:?} ({} overruns so far)",
+                    self.tick, self.overruns
+                );
+            }
+        }
+    }
+
+    /// Acts on something sent to the game loop, breaking once the server has shut down.
+    fn handle(&mut self, inbound: Inbound) -> ControlFlow<()> {
+        match inbound {
+            Inbound::Connected(connection) => {
+                self.pending
+                    .insert(connection.id(), (connection, Instant::now()));
+            }
+            Inbound::Message { id, message } => match self.pending.remove(&id) {
+                Some((connection, _)) => self.join(connection, message),
+                None => self.receive(id, message),
+            },
+            Inbound::Disconnected { id, error } => self.disconnect(id, error),
+            Inbound::Shutdown { reason } => {
+                self.shut_down(reason);
+
+                return ControlFlow::Break(());
+            }
+            Inbound::SetPassword(password) => {
+                match password {
+                    Some(_) => info!("The password has been changed"),
+                    None => info!("The password has been removed, anyone can join"),
+                }
+
+                self.settings.password = password;
+            }
+            Inbound::Command { command, reply } => {
+                let response = self.execute(command);
+                let _ = reply.send(response);
+            }
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    /// Lets everyone know the server is going away and sums up the match.
+    ///
+    /// Nobody is accepted anymore once the game loop is dropped, and their connections close as
+    /// soon as they have sent this last message.
+    fn shut_down(&mut self, reason: String) {
+        info!("Shutting down: {reason}");
+
+        let message = ServerMessage::Shutdown { reason };
+        for player in &self.players {
+            let _ = player.inform(message.clone());
+        }
+        for (connection, _) in self.pending.values() {
+            let _ = connection.send(message.clone());
+        }
+
+        info!(
+            "Match summary: {:?} after {} ticks in {:?} ({} overruns)",
+            self.phase,
+            self.tick,
+            self.started.elapsed(),
+            self.overruns
+        );
+
+        let away = self.away.iter().map(|(player, _)| player);
+        for player in self.players.iter().chain(away) {
+            info!(
+                "  {} ({}): {} kills, {} deaths",
+                player.name(),
+                player.id(),
+                player.kills(),
+                player.deaths()
+            );
+        }
+
+        info!("Stats: {STATS}");
+    }
+
+    fn join(&mut self, connection: Connection, message: ClientMessage) {
+        let (ClientMessage::Hello {
+            version, encodings, ..
+        }
+        | ClientMessage::Reconnect {
+            version, encodings, ..
+        }) = &message
+        else {
+            return refuse(connection, Error::UnexpectedMessage(message));
+        };
+
+        let version = *version;
+        connection.encoding().set(self.negotiate(encodings));
+
+        if version != PROTOCOL_VERSION {
+            return reject(
+                connection,
+                Rejection::VersionMismatch {
+                    server: PROTOCOL_VERSION,
+                },
+            );
+        }
+
+        match message {
+            ClientMessage::Hello {
+                name,
+                client_build,
+                password,
+                ..
+            } => {
+                if let Err(rejection) = self.check_name(&name) {
+                    return reject(connection, rejection);
+                }
+
+                // The address was checked on accepting, but may have been banned since. The name
+                // is whatever the player says it is, so a ban on it only holds until they pick
+                // another.
+                let banned = self
+                    .bans
+                    .read()
+                    .expect("the ban list is never poisoned")
+                    .find(Some(connection.ip()), Some(&name))
+                    .map(Ban::rejection);
+                if let Some(rejection) = banned {
+                    return reject(connection, rejection);
+                }
+
+                if let Err(rejection) = self.authenticate(connection.ip(), password.as_deref()) {
+                    return reject(connection, rejection);
+                }
+
+                if self.players.len() + self.away.len() >= *self.settings.players_allowed.end() {
+                    return reject(connection, Rejection::ServerFull);
+                }
+
+                let id = self.next_id;
+                self.next_id += 1;
+
+                let spawn = SPAWN_POINTS[id % SPAWN_POINTS.len()];
+                let player = Player::new(id, connection, name, spawn);
+
+                info!("{} ({id}) joined running {client_build}", player.name());
+                self.enter(player);
+            }
+            ClientMessage::Reconnect { token, .. } => self.rejoin(connection, token),
+            _ => unreachable!("only handshakes get this far"),
+        }
+    }
+
+    /// Makes sure a name can be shown to everyone, and tells its player apart from the rest.
+    fn check_name(&self, name: &str) -> Result<(), Rejection> {
+        let length = name.chars().count();
+        if length == 0
+            || length > MAX_NAME_LENGTH
+            || name.trim() != name
+            || name.chars().any(char::is_control)
+        {
+            return Err(Rejection::InvalidName);
+        }
+
+        let away = self.away.iter().map(|(player, _)| player);
+        if self.players.iter().chain(away).any(|p| p.name() == name) {
+            return Err(Rejection::NameTaken);
+        }
+
+        Ok(())
+    }
+
+    /// Checks the password of a private server, turning away addresses that keep getting it
+    /// wrong until they have waited a while.
+    fn authenticate(&mut self, ip: IpAddr, password: Option<&str>) -> Result<(), Rejection> {
+        let Some(expected) = self.settings.password.as_deref() else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        if self.attempts.is_locked_out(ip, now) {
+            return Err(Rejection::TooManyAttempts);
+        }
+
+        if password.is_some_and(|password| password_matches(password, expected)) {
+            return Ok(());
+        }
+
+        self.attempts.fail(ip, now);
+
+        warn!("{ip} got the password wrong");
+        Err(Rejection::BadPassword)
+    }
+
+    /// Picks the client's favourite of the encodings the server is willing to speak.
+    fn negotiate(&self, offered: &[Encoding]) -> Encoding {
+        offered
+            .iter()
+            .copied()
+            .find(|&encoding| encoding == self.settings.encoding || encoding == Encoding::Json)
+            .unwrap_or(Encoding::Json)
+    }
+
+    /// Puts a player back in their place, as long as their session is still around.
+    fn rejoin(&mut self, connection: Connection, token: String) {
+        // The old connection may not have noticed it is dead yet, in which case the new one takes over.
+        if let Some(index) = self.players.iter().position(|p| p.token() == token) {
+            let player = &mut self.players[index];
+            player.attach(connection);
+
+            info!(
+                "{} ({}) moved to a new connection",
+                player.name(),
+                player.id()
+            );
+            return self.greet(index);
+        }
+
+        let Some(index) = self.away.iter().position(|(p, _)| p.token() == token) else {
+            return reject(connection, Rejection::InvalidSession);
+        };
+
+        let (mut player, _) = self.away.remove(index);
+        player.attach(connection);
+
+        info!("{} ({}) reconnected", player.name(), player.id());
+        self.enter(player);
+    }
+
+    /// Lets a player into the match, letting everyone else know about them.
+    fn enter(&mut self, player: Player) {
+        self.broadcast(ServerMessage::Event(Event::Joined {
+            id: player.id(),
+            name: player.name().to_string(),
+        }));
+
+        self.players.push(player);
+        self.greet(self.players.len() - 1);
+    }
+
+    /// Welcomes a player, telling them who they are playing with and where the match is at.
+    fn greet(&mut self, index: usize) {
+        let player = &self.players[index];
+
+        let mut messages = vec![ServerMessage::Welcome {
+            id: player.id(),
+            token: player.token().to_string(),
+            position: *player.position(),
+            tick_rate: self.settings.tick_rate,
+            encoding: player.encoding(),
+            hostname: self.settings.hostname.clone(),
+            map: self.settings.map.clone(),
+            mode: self.settings.mode,
+        }];
+        for other in &self.players {
+            messages.push(ServerMessage::Event(Event::Joined {
+                id: other.id(),
+                name: other.name().to_string(),
+            }));
+
+            if other.is_ready() {
+                messages.push(ServerMessage::Event(Event::Ready {
+                    id: other.id(),
+                    ready: true,
+                }));
+            }
+        }
 
-            for (player, state) in self.players.iter_mut().zip(states) {
-                player.inform(state.as_bytes()).await?;
+        match self.phase {
+            Phase::Lobby => {}
+            Phase::Countdown(start) => messages.push(ServerMessage::Event(Event::Countdown {
+                seconds: start
+                    .saturating_duration_since(Instant::now())
+                    .as_secs_f64(),
+            })),
+            Phase::Live(_) => messages.push(ServerMessage::Event(Event::MatchStarted)),
+        }
+
+        if self.paused.is_some() {
+            messages.push(ServerMessage::Event(Event::Paused { paused: true }));
+        }
+
+        for message in messages {
+            if let Err(error) = player.inform(message) {
+                self.failed.push((player.id(), error));
+
+                return;
+            }
+        }
+    }
+
+    fn receive(&mut self, connection: usize, message: ClientMessage) {
+        let Some(player) = self
+            .players
+            .iter_mut()
+            .find(|p| p.is_connected_via(connection))
+        else {
+            return;
+        };
+
+        let id = player.id();
+
+        match message {
+            // Once the match is underway, there is nothing left to be ready for.
+            ClientMessage::Ready { .. } if matches!(self.phase, Phase::Live(_)) => {}
+            ClientMessage::Ready { ready } => {
+                player.set_ready(ready);
+                self.broadcast(ServerMessage::Event(Event::Ready { id, ready }));
+            }
+            // Nobody moves or shoots while the match is frozen.
+            ClientMessage::Input(_) | ClientMessage::Fire { .. } if self.paused.is_some() => {}
+            ClientMessage::Input(command) => player.queue(command),
+            ClientMessage::Fire { tick, yaw, pitch } => self.fire(id, tick, yaw, pitch),
+            // Only snapshots that were actually sent can be acknowledged.
+            ClientMessage::Ack { tick } if tick <= self.tick => player.acknowledge(tick),
+            ClientMessage::Ack { .. } => {}
+            ClientMessage::Chat { text } => match clean_chat(&text) {
+                Some(text) => self.broadcast(ServerMessage::Chat { id, text }),
+                None => debug!("Dropped an empty chat message from {}", player.name()),
+            },
+            ClientMessage::Ping { time } => {
+                if let Err(error) = player.inform(ServerMessage::Pong { time }) {
+                    self.failed.push((id, error));
+                }
+            }
+            ClientMessage::Pong { time } => {
+                let now = self.started.elapsed().as_millis() as u64;
+                let sample = Duration::from_millis(now.saturating_sub(time));
+                player.measure_round_trip(sample);
+            }
+            ClientMessage::Disconnect => self.remove(id, Error::Disconnected),
+            ClientMessage::Hello { .. } | ClientMessage::Reconnect { .. } => {
+                self.remove(id, Error::UnexpectedMessage(message))
+            }
+        }
+    }
+
+    /// Handles a shot, tracing it through the world as the shooter saw it at `tick`.
+    fn fire(&mut self, id: usize, tick: u64, yaw: f64, pitch: f64) {
+        let Some(shooter) = self.players.iter_mut().find(|p| p.id() == id) else {
+            return;
+        };
+
+        if !shooter.fire(Instant::now()) {
+            debug!("{} ({id}) cannot fire right now", shooter.name());
+
+            return;
+        }
+
+        // Rewind everyone else to where they were on the shooter's screen, but no further back
+        // than their connection explains, so lying about the tick buys nothing.
+        let unlag = (shooter.round_trip().unwrap_or_default() + UNLAG_SLACK).min(MAX_UNLAG);
+        let rewind = (unlag.as_secs_f64() * self.settings.tick_rate as f64).ceil() as u64;
+        let tick = tick.clamp(self.tick.saturating_sub(rewind), self.tick);
+        let Some(view) = self.history.iter().find(|s| s.tick == tick) else {
+            debug!("{id} fired at tick {tick}, which is too long ago to rewind to");
+
+            return;
+        };
+
+        let weapon = *shooter.weapon();
+        let ray = Ray::from_view(*shooter.position(), yaw, pitch);
+        let targets = view.players.iter().filter(|p| p.id != id && p.alive);
+
+        let Some(hit) = hitscan::trace(&ray, targets, weapon.max_range) else {
+            return;
+        };
+
+        // The target may have left or died since.
+        let Some(target) = self
+            .players
+            .iter_mut()
+            .find(|p| p.id() == hit.id && p.is_alive())
+        else {
+            return;
+        };
+
+        if target.shoot(&weapon, hit.distance, hit.headshot) {
+            info!("{} ({}) was killed by {id}", target.name(), target.id());
+
+            if let Some(shooter) = self.players.iter_mut().find(|p| p.id() == id) {
+                shooter.add_kill();
+            }
+
+            self.broadcast(ServerMessage::Event(Event::Killed {
+                id: hit.id,
+                by: id,
+                headshot: hit.headshot,
+            }));
+        }
+    }
+
+    /// Handles a connection going away, whether or not it belonged to a player.
+    fn disconnect(&mut self, connection: usize, error: Error) {
+        // Connections that never said hello can leave without anyone noticing.
+        if self.pending.remove(&connection).is_some() {
+            info!("Connection {connection} closed before joining: {error}");
+
+            return;
+        }
+
+        if let Some(player) = self.players.iter().find(|p| p.is_connected_via(connection)) {
+            self.remove(player.id(), error);
+        }
+    }
+
+    /// Removes a player from the match, letting everyone else know why they left.
+    fn remove(&mut self, id: usize, error: Error) {
+        let Some(index) = self.players.iter().position(|p| p.id() == id) else {
+            return;
+        };
+
+        let mut player = self.players.remove(index);
+        let reason = error.reason();
+        info!(
+            "{} ({id}) disconnected: {error} ({reason:?})",
+            player.name()
+        );
+
+        // Tell them why, in case their connection is still alive to hear it.
+        let _ = player.inform(ServerMessage::Disconnect { reason });
+        player.detach();
+
+        if reason.can_reconnect() {
+            self.away.push((player, Instant::now()));
+        }
+
+        self.broadcast(ServerMessage::Event(Event::Left { id, reason }));
+    }
+
+    /// Moves every player, putting back anyone whose prediction went astray and dealing with
+    /// anyone who reports a move they could not have made.
+    fn simulate(&mut self, delta: f64) {
+        let now = Instant::now();
+        for player in &mut self.players {
+            let result = player.simulate(
+                self.settings.authority,
+                self.settings.violation_response,
+                &DEFAULT_MOVEMENT,
+                delta,
+                FLOOR_HEIGHT,
+            );
+
+            if player.needs_correction(now) {
+                let correction = ServerMessage::Correction {
+                    position: *player.position(),
+                };
+
+                if let Err(error) = player.inform(correction) {
+                    self.failed.push((player.id(), error));
+                }
+            }
+
+            let Err(violation) = result else {
+                continue;
+            };
+
+            warn!("{} ({}) {violation}", player.name(), player.id());
+
+            let result = match self.settings.violation_response {
+                Response::Correct => player.inform(ServerMessage::Correction {
+                    position: *player.position(),
+                }),
+                Response::Warn => Ok(()),
+                Response::Kick => Err(violation.into()),
+            };
+
+            if let Err(error) = result {
+                self.failed.push((player.id(), error));
+            }
+        }
+    }
+
+    /// Starts the countdown once enough players are ready, the match once it runs out, and sends
+    /// everyone back to the lobby once the round is over. In the meantime, brings back those who
+    /// have been dead for long enough.
+    fn advance_phase(&mut self) {
+        let ready = self.players.len() >= *self.settings.players_allowed.start()
+            && self.players.iter().all(Player::is_ready);
+
+        match self.phase {
+            Phase::Lobby if ready => {
+                info!(
+                    "Everyone is ready, starting in {:?}",
+                    self.settings.countdown
+                );
+
+                self.phase = Phase::Countdown(Instant::now() + self.settings.countdown);
+                self.broadcast(ServerMessage::Event(Event::Countdown {
+                    seconds: self.settings.countdown.as_secs_f64(),
+                }));
+            }
+            Phase::Countdown(_) if !ready => {
+                info!("Not everyone is ready anymore, back to the lobby");
+
+                self.phase = Phase::Lobby;
+                self.broadcast(ServerMessage::Event(Event::CountdownCancelled));
+            }
+            Phase::Countdown(start) if Instant::now() >= start => {
+                info!("The match has started with {} players", self.players.len());
+
+                self.phase = Phase::Live(Instant::now());
+                self.start_match();
+                self.broadcast(ServerMessage::Event(Event::MatchStarted));
+            }
+            Phase::Live(started)
+                if self
+                    .settings
+                    .round_time
+                    .is_some_and(|time| started.elapsed() >= time) =>
+            {
+                info!("The round is over, back to the lobby");
+
+                self.end_round();
+                self.broadcast(ServerMessage::Event(Event::RoundEnded));
+            }
+            Phase::Live(_) => {
+                let now = Instant::now();
+                for player in &mut self.players {
+                    if player
+                        .died()
+                        .is_some_and(|died| now >= died + RESPAWN_DELAY)
+                    {
+                        respawn(player, &mut self.failed);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Puts everyone back at their spawn with a clean score, as what happened while waiting for
+    /// the match was only a warm-up.
+    fn start_match(&mut self) {
+        self.respawn_everyone();
+
+        let away = self.away.iter_mut().map(|(player, _)| player);
+        for player in self.players.iter_mut().chain(away) {
+            player.reset_score();
+        }
+    }
+
+    /// Sends everyone back to the lobby for the next round, waiting for them to ready up again.
+    fn end_round(&mut self) {
+        self.phase = Phase::Lobby;
+        self.respawn_everyone();
+
+        let away = self.away.iter_mut().map(|(player, _)| player);
+        for player in self.players.iter_mut().chain(away) {
+            player.set_ready(false);
+        }
+    }
+
+    /// Starts the round over, with everyone back at their spawn.
+    fn restart_round(&mut self) {
+        if let Phase::Live(_) = self.phase {
+            self.phase = Phase::Live(Instant::now());
+        }
+
+        self.respawn_everyone();
+    }
+
+    /// Puts everyone back at their spawn with full health.
+    fn respawn_everyone(&mut self) {
+        let away = self.away.iter_mut().map(|(player, _)| player);
+        for player in self.players.iter_mut().chain(away) {
+            respawn(player, &mut self.failed);
+        }
+    }
+
+    /// Sends every player the changes since the last snapshot they acknowledged, or the whole
+    /// snapshot if we no longer have that one.
+    fn send_snapshot(&mut self, snapshot: Snapshot) {
+        for player in &self.players {
+            let baseline = player
+                .acked()
+                .and_then(|tick| self.history.iter().find(|s| s.tick == tick));
+
+            let message = match baseline {
+                Some(baseline) => ServerMessage::Delta(snapshot.delta(baseline)),
+                None => ServerMessage::Snapshot(snapshot.clone()),
+            };
+            if let Err(error) = player.inform(message) {
+                self.failed.push((player.id(), error));
+            }
+        }
+
+        if self.history.len() == SNAPSHOT_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(snapshot);
+    }
+
+    fn broadcast(&mut self, message: ServerMessage) {
+        for player in &self.players {
+            if let Err(error) = player.inform(message.clone()) {
+                self.failed.push((player.id(), error));
             }
         }
     }
 }
+
+/// Puts a player back at their spawn with full health, telling them where that is.
+fn respawn(player: &mut Player, failed: &mut Vec<(usize, Error)>) {
+    let spawn = SPAWN_POINTS[player.id() % SPAWN_POINTS.len()];
+    player.respawn(spawn);
+
+    if let Err(error) = player.inform(ServerMessage::Correction { position: spawn }) {
+        failed.push((player.id(), error));
+    }
+}
+
+/// Makes a chat message safe to show to everyone, or nothing if there is nothing left to show.
+fn clean_chat(text: &str) -> Option<String> {
+    let text: String = text
+        .trim()
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(MAX_CHAT_LENGTH)
+        .collect();
+    let text = text.trim_end();
+
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Turns down a handshake for a reason the client can act on.
+fn reject(connection: Connection, rejection: Rejection) {
+    info!(
+        "Rejected connection {} from {}: {rejection:?}",
+        connection.id(),
+        connection.address()
+    );
+
+    let _ = connection.send(ServerMessage::Reject(rejection));
+}
+
+/// Turns away a connection that did not start with a handshake.
+fn refuse(connection: Connection, error: Error) {
+    let reason = error.reason();
+    info!(
+        "Refused connection {}: {error} ({reason:?})",
+        connection.id()
+    );
+
+    let _ = connection.send(ServerMessage::Disconnect { reason });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleans_up_chat() {
+        assert_eq!(clean_chat("  gg wp \n").as_deref(), Some("gg wp"));
+        assert_eq!(
+            clean_chat("one\ntwo\x1b[2Jthree").as_deref(),
+            Some("one two [2Jthree")
+        );
+        assert_eq!(clean_chat(""), None);
+        assert_eq!(clean_chat(" \t\r\n "), None);
+        assert_eq!(clean_chat("\0\x07"), None);
+    }
+
+    #[test]
+    fn cuts_chat_down_to_size() {
+        let long = "é".repeat(MAX_CHAT_LENGTH + 50);
+        let text = clean_chat(&long).unwrap();
+        assert_eq!(text.chars().count(), MAX_CHAT_LENGTH);
+
+        let padded = format!("{}    x", "a".repeat(MAX_CHAT_LENGTH - 2));
+        assert_eq!(
+            clean_chat(&padded).unwrap(),
+            "a".repeat(MAX_CHAT_LENGTH - 2)
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use std::io;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::time;
+
+/// Held by every task that writes to clients, so shutting down can wait for them to finish.
+#[derive(Debug, Clone)]
+pub struct FlushGuard {
+    _sender: mpsc::Sender<()>,
+}
+
+/// Resolves once every [`FlushGuard`] is gone.
+#[derive(Debug)]
+pub struct Flushed(mpsc::Receiver<()>);
+
+pub fn flush_guard() -> (FlushGuard, Flushed) {
+    let (sender, receiver) = mpsc::channel(1);
+
+    (FlushGuard { _sender: sender }, Flushed(receiver))
+}
+
+impl Flushed {
+    /// Waits for the writers to finish, returning whether they did so in time.
+    pub async fn wait(mut self, timeout: Duration) -> bool {
+        time::timeout(timeout, self.0.recv()).await.is_ok()
+    }
+}
+
+/// Waits until the server is asked to stop, returning the name of the signal that asked.
+#[cfg(unix)]
+pub async fn requested() -> io::Result<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.map(|_| "SIGINT"),
+        _ = terminate.recv() => Ok("SIGTERM"),
+    }
+}
+
+/// Waits until the server is asked to stop, returning the name of the signal that asked.
+#[cfg(not(unix))]
+pub async fn requested() -> io::Result<&'static str> {
+    tokio::signal::ctrl_c().await.map(|_| "Ctrl-C")
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters for things worth keeping an eye on, updated from every task.
+#[derive(Debug)]
+pub struct Stats {
+    /// Messages dropped for going over their connection's budget.
+    pub throttled: AtomicU64,
+    /// Messages turned down for being larger than allowed.
+    pub oversized: AtomicU64,
+    /// Connections dropped for going over their budget too often.
+    pub flooders: AtomicU64,
+}
+
+pub static STATS: Stats = Stats {
+    throttled: AtomicU64::new(0),
+    oversized: AtomicU64::new(0),
+    flooders: AtomicU64::new(0),
+};
+
+/// Counts one more occurrence of whatever `counter` keeps track of.
+pub fn record(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} messages throttled, {} oversized, {} flooders disconnected",
+            self.throttled.load(Ordering::Relaxed),
+            self.oversized.load(Ordering::Relaxed),
+            self.flooders.load(Ordering::Relaxed),
+        )
+    }
+}